@@ -0,0 +1,88 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::epub::EpubBook;
+use crate::model::Result;
+
+/// Writes pages into a hidden temporary archive next to `path`, which is
+/// renamed into place once every page was written.
+pub struct ArchiveWriter {
+    writer: ZipWriter<fs::File>,
+    path: PathBuf,
+    tmp_path: PathBuf,
+    epub: Option<EpubBook>,
+}
+
+impl ArchiveWriter {
+    pub fn create(path: PathBuf, epub: Option<EpubBook>) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let tmp_path = get_tmp_path(&path);
+        let mut writer = ZipWriter::new(fs::File::create(&tmp_path)?);
+
+        if let Some(epub) = &epub {
+            epub.start(&mut writer)?;
+        }
+
+        Ok(ArchiveWriter {
+            writer,
+            path,
+            tmp_path,
+            epub,
+        })
+    }
+
+    pub fn tmp_path(&self) -> &Path {
+        &self.tmp_path
+    }
+
+    pub fn set_comment(&mut self, comment: String) {
+        self.writer.set_comment(comment);
+    }
+
+    pub fn add_page(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        match &mut self.epub {
+            Some(epub) => epub.add_image(&mut self.writer, name, data),
+            None => {
+                let options = entry_options(CompressionMethod::Stored).unix_permissions(0o644);
+                self.writer.start_file(name, options)?;
+                self.writer.write_all(data)?;
+
+                Ok(())
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Result<PathBuf> {
+        if let Some(epub) = &self.epub {
+            epub.finish(&mut self.writer)?;
+        }
+
+        self.writer.finish()?;
+        fs::rename(&self.tmp_path, &self.path)?;
+
+        Ok(self.path)
+    }
+}
+
+// a fixed timestamp keeps archives of the same pages byte for byte identical
+pub fn entry_options(method: CompressionMethod) -> FileOptions {
+    FileOptions::default()
+        .compression_method(method)
+        .last_modified_time(zip::DateTime::default())
+}
+
+fn get_tmp_path(path: &Path) -> PathBuf {
+    let mut file_name = OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(".part");
+
+    path.with_file_name(file_name)
+}
@@ -0,0 +1,211 @@
+use std::time::{Duration, Instant};
+
+use futures::lock::Mutex;
+use log::{debug, warn};
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::constants::{APP_DIR_NAME, MANGADEX_AUTH_URL};
+use crate::http;
+use crate::model::{
+    AuthStatus, Result, ServiceError, StoredCredentials, TokenError, TokenResponse,
+};
+
+const KEYRING_USER: &str = "mangadex";
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+struct Session {
+    credentials: StoredCredentials,
+    access_token: String,
+    expires_at: Instant,
+}
+
+impl Session {
+    fn new(credentials: StoredCredentials, token: TokenResponse) -> Self {
+        Session {
+            credentials: StoredCredentials {
+                refresh_token: token.refresh_token,
+                ..credentials
+            },
+            access_token: token.access_token,
+            expires_at: Instant::now() + Duration::from_secs(token.expires_in),
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        Instant::now() + TOKEN_EXPIRY_MARGIN >= self.expires_at
+    }
+}
+
+#[derive(Default)]
+pub struct Auth {
+    session: Mutex<Option<Session>>,
+}
+
+impl Auth {
+    pub async fn login(
+        &self,
+        username: &str,
+        password: &str,
+        client_id: &str,
+        client_secret: &str,
+    ) -> Result<AuthStatus> {
+        let token = request_token(&[
+            ("grant_type", "password"),
+            ("username", username),
+            ("password", password),
+            ("client_id", client_id),
+            ("client_secret", client_secret),
+        ])
+        .await?;
+
+        let credentials = StoredCredentials {
+            username: username.to_owned(),
+            client_id: client_id.to_owned(),
+            client_secret: client_secret.to_owned(),
+            refresh_token: String::new(),
+        };
+        let session = Session::new(credentials, token);
+        store_credentials(&session.credentials)?;
+
+        debug!("logged in as {username}");
+        *self.session.lock().await = Some(session);
+
+        Ok(AuthStatus {
+            logged_in: true,
+            username: Some(username.to_owned()),
+        })
+    }
+
+    pub async fn logout(&self) -> Result<()> {
+        *self.session.lock().await = None;
+        delete_credentials()
+    }
+
+    pub async fn status(&self) -> Result<AuthStatus> {
+        let username = match self.session.lock().await.as_ref() {
+            Some(session) => Some(session.credentials.username.to_owned()),
+            None => load_credentials()?.map(|credentials| credentials.username),
+        };
+
+        Ok(AuthStatus {
+            logged_in: username.is_some(),
+            username,
+        })
+    }
+
+    pub async fn access_token(&self) -> Result<String> {
+        let mut session = self.session.lock().await;
+
+        if let Some(current) = session.as_ref().filter(|session| !session.is_expired()) {
+            return Ok(current.access_token.to_owned());
+        }
+
+        let credentials = match session.take() {
+            Some(expired) => expired.credentials,
+            None => load_credentials()?.ok_or(ServiceError::Unauthorized)?,
+        };
+
+        let token = match refresh_token(&credentials).await {
+            Ok(token) => token,
+            Err(ServiceError::AuthError(e)) => {
+                warn!("failed to refresh session, logging out: {e}");
+                delete_credentials()?;
+                return Err(ServiceError::Unauthorized);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let refreshed = Session::new(credentials, token);
+        store_credentials(&refreshed.credentials)?;
+        let access_token = refreshed.access_token.to_owned();
+        *session = Some(refreshed);
+
+        Ok(access_token)
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let token = self.access_token().await?;
+        let response = http::client()?.get(url).bearer_auth(token).send().await?;
+
+        Ok(check_status(response)?.json().await?)
+    }
+
+    pub async fn post<B: Serialize>(&self, url: &str, body: &B) -> Result<()> {
+        let token = self.access_token().await?;
+        let response = http::client()?
+            .post(url)
+            .bearer_auth(token)
+            .json(body)
+            .send()
+            .await?;
+        check_status(response)?;
+
+        Ok(())
+    }
+}
+
+fn check_status(response: Response) -> Result<Response> {
+    if response.status() == StatusCode::UNAUTHORIZED {
+        return Err(ServiceError::Unauthorized);
+    }
+
+    http::check_status(response)
+}
+
+async fn refresh_token(credentials: &StoredCredentials) -> Result<TokenResponse> {
+    request_token(&[
+        ("grant_type", "refresh_token"),
+        ("refresh_token", &credentials.refresh_token),
+        ("client_id", &credentials.client_id),
+        ("client_secret", &credentials.client_secret),
+    ])
+    .await
+}
+
+async fn request_token(params: &[(&str, &str)]) -> Result<TokenResponse> {
+    let response = http::client()?
+        .post(MANGADEX_AUTH_URL)
+        .form(params)
+        .send()
+        .await?;
+    if response.status() == StatusCode::TOO_MANY_REQUESTS {
+        return Err(ServiceError::RateLimited {
+            retry_after: http::retry_after(&response),
+        });
+    }
+
+    if response.status().is_success() {
+        return Ok(response.json().await?);
+    }
+
+    let error: TokenError = response.json().await?;
+    Err(ServiceError::AuthError(
+        error.error_description.unwrap_or(error.error),
+    ))
+}
+
+fn credentials_entry() -> Result<keyring::Entry> {
+    Ok(keyring::Entry::new(APP_DIR_NAME, KEYRING_USER)?)
+}
+
+fn load_credentials() -> Result<Option<StoredCredentials>> {
+    match credentials_entry()?.get_password() {
+        Ok(secret) => Ok(serde_json::from_str(&secret).ok()),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn store_credentials(credentials: &StoredCredentials) -> Result<()> {
+    credentials_entry()?.set_password(&serde_json::to_string(credentials)?)?;
+    Ok(())
+}
+
+fn delete_credentials() -> Result<()> {
+    match credentials_entry()?.delete_password() {
+        Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
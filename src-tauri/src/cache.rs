@@ -0,0 +1,296 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use log::{debug, warn};
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::APP_DIR_NAME;
+use crate::http;
+use crate::model::{Result, ServiceError};
+use crate::time::unix_now;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    url: String,
+    etag: Option<String>,
+    fetched_at: u64,
+    body: String,
+}
+
+impl CacheEntry {
+    fn is_fresh(&self, ttl: Duration) -> bool {
+        unix_now().saturating_sub(self.fetched_at) < ttl.as_secs()
+    }
+}
+
+pub async fn get_json<T: DeserializeOwned>(url: &str, ttl: Duration) -> Result<T> {
+    let body = get_body(url, ttl).await?;
+
+    Ok(serde_json::from_str(&body)?)
+}
+
+pub fn clear() -> Result<()> {
+    if let Some(dir) = get_app_cache_dir() {
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn get_body(url: &str, ttl: Duration) -> Result<String> {
+    let path = get_entry_path(url);
+    let cached = path.as_deref().and_then(|path| read_entry(path, url));
+
+    if let Some(entry) = cached.as_ref().filter(|entry| entry.is_fresh(ttl)) {
+        debug!("cache hit: {url}");
+        return Ok(entry.body.clone());
+    }
+
+    let mut request = http::client()?.get(url);
+    if let Some(etag) = cached.as_ref().and_then(|entry| entry.etag.as_ref()) {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            return match cached {
+                Some(entry) => {
+                    warn!("request to {url} failed, using stale cache: {e}");
+                    Ok(entry.body)
+                }
+                None => Err(e.into()),
+            }
+        }
+    };
+
+    let status = response.status();
+
+    if status == StatusCode::NOT_MODIFIED {
+        if let Some(mut entry) = cached {
+            debug!("cache revalidated: {url}");
+            entry.fetched_at = unix_now();
+            write_entry(path.as_deref(), &entry);
+            return Ok(entry.body);
+        }
+    }
+
+    if status.is_server_error() {
+        if let Some(entry) = cached {
+            warn!("request to {url} failed with {status}, using stale cache");
+            return Ok(entry.body);
+        }
+    }
+
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        if let Some(entry) = cached {
+            warn!("rate limited on {url}, using stale cache");
+            return Ok(entry.body);
+        }
+
+        return Err(ServiceError::RateLimited {
+            retry_after: http::retry_after(&response),
+        });
+    }
+
+    let etag = response
+        .headers()
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    let body = response.text().await?;
+
+    if status.is_success() {
+        let entry = CacheEntry {
+            url: url.to_owned(),
+            etag,
+            fetched_at: unix_now(),
+            body,
+        };
+        write_entry(path.as_deref(), &entry);
+        return Ok(entry.body);
+    }
+
+    Ok(body)
+}
+
+fn get_app_cache_dir() -> Option<PathBuf> {
+    tauri::api::path::cache_dir().map(|dir| dir.join(APP_DIR_NAME))
+}
+
+fn get_cache_dir() -> Option<PathBuf> {
+    get_app_cache_dir().map(|dir| dir.join("api"))
+}
+
+/// Removes cached pages older than `max_age`, then the oldest ones until the
+/// rest fits in `max_bytes`.
+pub fn prune_pages(max_bytes: u64, max_age: Duration) -> Result<()> {
+    match get_app_cache_dir() {
+        Some(dir) => prune_dir(&dir.join("pages"), max_bytes, max_age),
+        None => Ok(()),
+    }
+}
+
+fn prune_dir(dir: &Path, max_bytes: u64, max_age: Duration) -> Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    let now = SystemTime::now();
+    let mut files = Vec::new();
+    let mut chapters = Vec::new();
+
+    for chapter in fs::read_dir(dir)? {
+        let chapter = chapter?.path();
+        if !chapter.is_dir() {
+            continue;
+        }
+
+        for page in fs::read_dir(&chapter)? {
+            let page = page?;
+            let metadata = page.metadata()?;
+            files.push((
+                metadata.modified().unwrap_or(now),
+                metadata.len(),
+                page.path(),
+            ));
+        }
+        chapters.push(chapter);
+    }
+
+    for path in select_expired(files, now, max_bytes, max_age) {
+        fs::remove_file(path)?;
+    }
+
+    for chapter in chapters {
+        // only succeeds for chapters left empty
+        let _ = fs::remove_dir(chapter);
+    }
+
+    Ok(())
+}
+
+// pages are (modified, size, path), the selected ones are the oldest
+fn select_expired(
+    mut files: Vec<(SystemTime, u64, PathBuf)>,
+    now: SystemTime,
+    max_bytes: u64,
+    max_age: Duration,
+) -> Vec<PathBuf> {
+    // oldest first
+    files.sort();
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    let mut pruned = Vec::new();
+
+    for (modified, len, path) in files {
+        let expired = now
+            .duration_since(modified)
+            .map_or(false, |age| age > max_age);
+        if !expired && total <= max_bytes {
+            break;
+        }
+
+        pruned.push(path);
+        total -= len;
+    }
+
+    pruned
+}
+
+pub fn get_pages_dir(chapter_id: &str) -> Option<PathBuf> {
+    get_app_cache_dir().map(|dir| dir.join("pages").join(chapter_id))
+}
+
+fn get_entry_path(url: &str) -> Option<PathBuf> {
+    get_cache_dir().map(|dir| dir.join(format!("{:016x}.json", fnv1a(url.as_bytes()))))
+}
+
+// file names have to stay the same across toolchain upgrades, which
+// `DefaultHasher` does not promise
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn read_entry(path: &Path, url: &str) -> Option<CacheEntry> {
+    let data = fs::read(path).ok()?;
+    let entry: CacheEntry = serde_json::from_slice(&data).ok()?;
+
+    // different urls may share a hash
+    (entry.url == url).then_some(entry)
+}
+
+fn write_entry(path: Option<&Path>, entry: &CacheEntry) {
+    if let Some(path) = path {
+        if let Err(e) = save_entry(path, entry) {
+            warn!("failed to write cache entry for {}: {e}", entry.url);
+        }
+    }
+}
+
+fn save_entry(path: &Path, entry: &CacheEntry) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(path, serde_json::to_vec(entry)?)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hashes_urls_stably() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn selects_oldest_pages_over_the_limit() {
+        let now = SystemTime::now();
+        let page = |age: u64, name: &str| (now - Duration::from_secs(age), 10, PathBuf::from(name));
+        let files = vec![page(10, "2.jpg"), page(20, "1.jpg"), page(5, "3.jpg")];
+
+        assert_eq!(
+            select_expired(files.clone(), now, 20, Duration::from_secs(60)),
+            [PathBuf::from("1.jpg")]
+        );
+        assert_eq!(
+            select_expired(files.clone(), now, 100, Duration::from_secs(8)),
+            [PathBuf::from("1.jpg"), PathBuf::from("2.jpg")]
+        );
+        assert!(select_expired(files, now, 30, Duration::from_secs(60)).is_empty());
+    }
+
+    #[test]
+    fn removes_pruned_pages_and_empty_chapters() {
+        let dir = std::env::temp_dir().join(format!("pages-{}", std::process::id()));
+        let chapter = dir.join("chapter");
+        fs::create_dir_all(&chapter).unwrap();
+
+        for name in ["1.jpg", "2.jpg", "3.jpg"] {
+            fs::write(chapter.join(name), [0; 10]).unwrap();
+        }
+
+        prune_dir(&dir, 20, Duration::from_secs(60)).unwrap();
+        let left = fs::read_dir(&chapter).unwrap().count();
+
+        prune_dir(&dir, 0, Duration::from_secs(60)).unwrap();
+        let emptied = !chapter.exists();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(left, 2);
+        assert!(emptied);
+    }
+}
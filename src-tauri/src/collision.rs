@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::model::ChapterProps;
+use crate::processing::OutputProfile;
+
+/// What to do when the archive of a chapter already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CollisionPolicy {
+    Skip,
+    #[default]
+    Overwrite,
+    /// Keeps both archives by adding a ` (2)`, ` (3)`... suffix to the new one.
+    Rename,
+    /// Replaces the archive only when it holds another release of the chapter,
+    /// e.g. one from a different scanlation group, or a lower quality copy.
+    ReplaceOtherRelease,
+}
+
+/// Release the archive was made from, kept in the zip comment.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReleaseInfo {
+    #[serde(default)]
+    pub manga_id: Option<String>,
+    pub chapter_id: String,
+    pub group_id: Option<String>,
+    /// Encoder quality the pages were written with, 100 for untouched pages.
+    #[serde(default)]
+    pub quality: Option<u8>,
+}
+
+impl ReleaseInfo {
+    pub fn new(chapter: &ChapterProps, profile: &OutputProfile) -> Self {
+        ReleaseInfo {
+            manga_id: chapter.manga_id.to_owned(),
+            chapter_id: chapter.id.to_owned(),
+            group_id: chapter.group_id.to_owned(),
+            quality: Some(profile.effective_quality()),
+        }
+    }
+
+    pub fn read(path: &Path) -> Option<ReleaseInfo> {
+        let archive = zip::ZipArchive::new(fs::File::open(path).ok()?).ok()?;
+        serde_json::from_slice(archive.comment()).ok()
+    }
+
+    // archives written before the quality was recorded are never replaced
+    // just for their quality
+    fn replaces(&self, existing: &ReleaseInfo) -> bool {
+        let better_quality = match (self.quality, existing.quality) {
+            (Some(quality), Some(existing)) => quality > existing,
+            _ => false,
+        };
+
+        self.chapter_id != existing.chapter_id
+            || self.group_id != existing.group_id
+            || better_quality
+    }
+}
+
+/// Picks the path to download a chapter to, or `None` when it should be skipped.
+///
+/// Paths handed out earlier in the same batch are never reused, so chapters
+/// sharing a name don't clobber each other.
+pub fn resolve_archive_path(
+    path: PathBuf,
+    extension: &str,
+    release: &ReleaseInfo,
+    policy: CollisionPolicy,
+    reserved: &mut HashSet<PathBuf>,
+) -> Option<PathBuf> {
+    let resolved = if reserved.contains(&path) {
+        unique_path(&path, extension, reserved)
+    } else if !path.exists() {
+        path
+    } else {
+        match policy {
+            CollisionPolicy::Skip => {
+                debug!("skipping existing {}", path.display());
+                return None;
+            }
+            CollisionPolicy::Overwrite => path,
+            CollisionPolicy::Rename => unique_path(&path, extension, reserved),
+            CollisionPolicy::ReplaceOtherRelease => match ReleaseInfo::read(&path) {
+                Some(existing) if !release.replaces(&existing) => {
+                    debug!("{} is already up to date", path.display());
+                    return None;
+                }
+                Some(_) => path,
+                None => {
+                    warn!("unknown release in {}, keeping it", path.display());
+                    return None;
+                }
+            },
+        }
+    };
+
+    reserved.insert(resolved.clone());
+
+    Some(resolved)
+}
+
+fn unique_path(path: &Path, extension: &str, reserved: &HashSet<PathBuf>) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stem = file_name
+        .strip_suffix(&format!(".{extension}"))
+        .unwrap_or(&file_name);
+
+    (2..)
+        .map(|copy| path.with_file_name(format!("{stem} ({copy}).{extension}")))
+        .find(|candidate| !candidate.exists() && !reserved.contains(candidate))
+        .expect("unbounded range always yields a free path")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("collision-{}-{name}", std::process::id()))
+    }
+
+    fn release(chapter_id: &str, group_id: &str, quality: Option<u8>) -> ReleaseInfo {
+        ReleaseInfo {
+            manga_id: None,
+            chapter_id: chapter_id.to_owned(),
+            group_id: Some(group_id.to_owned()),
+            quality,
+        }
+    }
+
+    #[test]
+    fn renames_duplicates_within_batch() {
+        let path = temp_path("Chapter 1.kepub.epub");
+        let mut reserved = HashSet::new();
+
+        let first = resolve_archive_path(
+            path.clone(),
+            "kepub.epub",
+            &release("a", "g", None),
+            CollisionPolicy::Skip,
+            &mut reserved,
+        );
+        let second = resolve_archive_path(
+            path.clone(),
+            "kepub.epub",
+            &release("b", "g", None),
+            CollisionPolicy::Skip,
+            &mut reserved,
+        );
+
+        assert_eq!(first, Some(path.clone()));
+        assert_eq!(
+            second,
+            Some(path.with_file_name(format!(
+                "collision-{}-Chapter 1 (2).kepub.epub",
+                std::process::id()
+            )))
+        );
+    }
+
+    #[test]
+    fn applies_policy_to_existing_archives() {
+        let path = temp_path("existing.cbz");
+        fs::write(&path, b"").unwrap();
+
+        let new_release = release("a", "g", None);
+        let resolve = |policy| {
+            resolve_archive_path(
+                path.clone(),
+                "cbz",
+                &new_release,
+                policy,
+                &mut HashSet::new(),
+            )
+        };
+        let skipped = resolve(CollisionPolicy::Skip);
+        let overwritten = resolve(CollisionPolicy::Overwrite);
+        let renamed = resolve(CollisionPolicy::Rename);
+        let unknown_release = resolve(CollisionPolicy::ReplaceOtherRelease);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(skipped, None);
+        assert_eq!(overwritten, Some(path.clone()));
+        assert_ne!(renamed, Some(path));
+        assert_eq!(unknown_release, None);
+    }
+
+    #[test]
+    fn replaces_other_groups_and_lower_quality() {
+        let existing = release("a", "g", Some(80));
+
+        assert!(!release("a", "g", Some(80)).replaces(&existing));
+        assert!(!release("a", "g", Some(60)).replaces(&existing));
+        assert!(release("a", "g", Some(95)).replaces(&existing));
+        assert!(release("a", "other", Some(60)).replaces(&existing));
+        assert!(release("b", "g", Some(80)).replaces(&existing));
+        assert!(!release("a", "g", Some(95)).replaces(&release("a", "g", None)));
+    }
+}
@@ -1,45 +1,38 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::result;
 
 use log::{debug, error};
-use serde::Serialize;
-use thiserror::Error;
+use tauri::{AppHandle, Manager, State};
 
+use crate::auth::Auth;
+use crate::devices::{device_profiles, find_device_profile, DeviceProfile};
+use crate::follows::Follows;
+use crate::library::{Library, LibraryEntry, ScanReport};
+use crate::limits::RequestLimiter;
 use crate::model::{
-    AggregateResponse, ChapterProps, ChaptersResponse, Manga, MangaView, ServiceError,
+    AggregateResponse, AuthStatus, ChapterAggregate, ChapterProps, ChaptersResponse, CommandError,
+    DownloadOptions, DownloadReport, FollowedManga, Locales, Manga, MangaView, ReadMarkersSync,
 };
-use crate::service;
-
-#[derive(Debug, Error, Serialize)]
-pub enum CommandError {
-    #[error("invalid arguments: {}", .0)]
-    InvalidArguments(String),
-    #[error("internal app error: {}", .0)]
-    Internal(String),
-}
-
-impl From<ServiceError> for CommandError {
-    fn from(e: ServiceError) -> Self {
-        use ServiceError::*;
-
-        match e {
-            InvalidArguments(e) => Self::InvalidArguments(e),
-            e => Self::Internal(e.to_string()),
-        }
-    }
-}
+use crate::online::OnlineReader;
+use crate::processing::OutputProfile;
+use crate::progress::{ChapterProgress, ReadProgress};
+use crate::reader::{ArchiveView, PageView, Reader};
+use crate::settings::{Settings, SettingsStore, SETTINGS_CHANGED_EVENT};
+use crate::{cache, http, reports, service};
 
 pub type Result<T> = result::Result<T, CommandError>;
 
 #[tauri::command]
-pub async fn search(query: &str) -> Result<Vec<MangaView>> {
+pub async fn search(query: &str, locales: Locales) -> Result<Vec<MangaView>> {
     debug!("searching for \"{query}\"");
 
-    Ok(service::search(query).await?)
+    Ok(service::search(query, &locales).await?)
 }
 
 #[tauri::command]
-pub async fn get_manga(id: &str) -> Result<Manga> {
-    Ok(service::get_manga(id).await?)
+pub async fn get_manga(id: &str, locales: Locales) -> Result<Manga> {
+    Ok(service::get_manga(id, &locales).await?)
 }
 
 #[tauri::command]
@@ -53,14 +46,346 @@ pub async fn get_chapters(
     Ok(service::fetch_feed(manga_id, lang, limit, offset).await?)
 }
 
+// arguments override the saved settings for a single download
+#[tauri::command]
+pub async fn download(
+    settings: State<'_, SettingsStore>,
+    library: State<'_, Library>,
+    limiter: State<'_, RequestLimiter>,
+    chapters: Vec<ChapterProps>,
+    options: Option<DownloadOptions>,
+) -> Result<DownloadReport> {
+    let saved = settings.get();
+    let options = options.unwrap_or_default();
+    let collision = options.collision.unwrap_or(saved.collision);
+    let (profile, device) = download_profile(&saved, options)?;
+
+    Ok(service::download(&library, &limiter, chapters, profile, device, collision).await?)
+}
+
+// the options' profile is partial, the fields it names replace the ones of
+// the saved profile or of the device, stitching is left as saved when unset
+fn download_profile(
+    saved: &Settings,
+    options: DownloadOptions,
+) -> Result<(OutputProfile, Option<DeviceProfile>)> {
+    let device = options
+        .device
+        .or_else(|| saved.device.clone())
+        .as_deref()
+        .map(find_device_profile)
+        .transpose()?;
+
+    let profile = match &device {
+        Some(device) => device.output_profile(),
+        None => saved.output.clone(),
+    };
+    let profile = match options.profile {
+        Some(overrides) => profile.with_overrides(overrides)?,
+        None => profile,
+    };
+    let profile = match options.stitch_strips {
+        Some(stitch) => profile.with_stitching(stitch),
+        None => profile,
+    };
+
+    Ok((profile, device))
+}
+
+#[tauri::command]
+pub async fn cancel_downloads(limiter: State<'_, RequestLimiter>) -> Result<()> {
+    limiter.cancel_downloads();
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn scan_library(
+    settings: State<'_, SettingsStore>,
+    library: State<'_, Library>,
+    folders: Option<Vec<PathBuf>>,
+    lang: Option<String>,
+) -> Result<ScanReport> {
+    let saved = settings.get();
+    let folders = folders.unwrap_or(saved.library_folders);
+    let lang = lang.unwrap_or(saved.chapter_language);
+    let locales = Locales::new(saved.locales);
+
+    Ok(service::scan_library(&library, folders, &lang, &locales).await?)
+}
+
+#[tauri::command]
+pub async fn get_library(library: State<'_, Library>, manga_id: &str) -> Result<Vec<LibraryEntry>> {
+    Ok(library.manga(manga_id))
+}
+
+#[tauri::command]
+pub async fn get_settings(settings: State<'_, SettingsStore>) -> Result<Settings> {
+    Ok(settings.get())
+}
+
+#[tauri::command]
+pub async fn update_settings(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    limiter: State<'_, RequestLimiter>,
+    settings: Settings,
+) -> Result<Settings> {
+    settings.validate()?;
+    // built up front so an unreadable certificate bundle rejects the update
+    let client = http::build_client(&settings.network)?;
+    let settings = store.update(settings)?;
+
+    http::set_client(client);
+    reports::set_enabled(settings.network.report_frames);
+    limiter.configure(settings.concurrency.clone());
+    limiter.set_schedule(settings.schedule.clone());
+
+    if let Err(e) = app.emit_all(SETTINGS_CHANGED_EVENT, &settings) {
+        error!("failed to notify about changed settings: {e}");
+    }
+
+    Ok(settings)
+}
+
 #[tauri::command]
-pub async fn download(chapters: Vec<ChapterProps>) -> Result<()> {
-    service::download(chapters).await?;
+pub async fn set_proxy_password(
+    store: State<'_, SettingsStore>,
+    password: Option<String>,
+) -> Result<()> {
+    http::set_proxy_password(password.as_deref())?;
+    http::configure(&store.get().network)?;
+
     Ok(())
 }
 
+#[tauri::command]
+pub async fn get_device_profiles() -> Result<Vec<DeviceProfile>> {
+    Ok(device_profiles())
+}
+
+#[tauri::command]
+pub async fn clear_cache() -> Result<()> {
+    Ok(cache::clear()?)
+}
+
 #[tauri::command]
 pub async fn aggregate(id: &str, lang: &str) -> Result<AggregateResponse> {
     let res = service::aggregate(id, lang).await?;
     Ok(res)
 }
+
+#[tauri::command]
+pub async fn open_archive(path: PathBuf, reader: State<'_, Reader>) -> Result<ArchiveView> {
+    debug!("opening archive {}", path.display());
+    Ok(reader.open(&path)?)
+}
+
+#[tauri::command]
+pub async fn get_archive_pages(id: &str, reader: State<'_, Reader>) -> Result<Vec<PageView>> {
+    Ok(reader.pages(id)?)
+}
+
+#[tauri::command]
+pub async fn prefetch_pages(
+    id: &str,
+    from: usize,
+    count: usize,
+    reader: State<'_, Reader>,
+) -> Result<()> {
+    Ok(reader.prefetch(id, from, count)?)
+}
+
+#[tauri::command]
+pub async fn close_archive(id: &str, reader: State<'_, Reader>) -> Result<()> {
+    reader.close(id);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn open_online_chapter(
+    chapter_id: &str,
+    online: State<'_, OnlineReader>,
+) -> Result<Vec<PageView>> {
+    debug!("opening chapter {chapter_id} for online reading");
+    Ok(online.open(chapter_id).await?)
+}
+
+// fetches a page into the cache so its protocol url can be served right away
+#[tauri::command]
+pub async fn load_online_page(
+    chapter_id: &str,
+    index: usize,
+    online: State<'_, OnlineReader>,
+    limiter: State<'_, RequestLimiter>,
+) -> Result<()> {
+    online.page(chapter_id, index, &limiter).await?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn close_online_chapter(chapter_id: &str, online: State<'_, OnlineReader>) -> Result<()> {
+    online.close(chapter_id);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_read_progress(
+    manga_id: &str,
+    progress: State<'_, ReadProgress>,
+) -> Result<HashMap<String, ChapterProgress>> {
+    Ok(progress.manga(manga_id))
+}
+
+#[tauri::command]
+pub async fn mark_chapters(
+    manga_id: &str,
+    chapter_ids: Vec<String>,
+    read: bool,
+    progress: State<'_, ReadProgress>,
+) -> Result<()> {
+    debug!(
+        "marking {} chapters of {manga_id} as read: {read}",
+        chapter_ids.len()
+    );
+    Ok(progress.mark(manga_id, &chapter_ids, read)?)
+}
+
+#[tauri::command]
+pub async fn set_last_page(
+    manga_id: &str,
+    chapter_id: &str,
+    page: u32,
+    progress: State<'_, ReadProgress>,
+) -> Result<()> {
+    Ok(progress.set_last_page(manga_id, chapter_id, page)?)
+}
+
+#[tauri::command]
+pub async fn next_unread_chapter(
+    manga_id: &str,
+    lang: &str,
+    progress: State<'_, ReadProgress>,
+) -> Result<Option<ChapterAggregate>> {
+    Ok(service::next_unread_chapter(&progress, manga_id, lang).await?)
+}
+
+#[tauri::command]
+pub async fn unread_chapters(
+    manga_id: &str,
+    lang: &str,
+    progress: State<'_, ReadProgress>,
+) -> Result<Vec<ChapterAggregate>> {
+    Ok(service::unread_chapters(&progress, manga_id, lang).await?)
+}
+
+#[tauri::command]
+pub async fn login(
+    username: &str,
+    password: &str,
+    client_id: &str,
+    client_secret: &str,
+    auth: State<'_, Auth>,
+) -> Result<AuthStatus> {
+    Ok(auth
+        .login(username, password, client_id, client_secret)
+        .await?)
+}
+
+#[tauri::command]
+pub async fn logout(auth: State<'_, Auth>) -> Result<()> {
+    Ok(auth.logout().await?)
+}
+
+#[tauri::command]
+pub async fn auth_status(auth: State<'_, Auth>) -> Result<AuthStatus> {
+    Ok(auth.status().await?)
+}
+
+#[tauri::command]
+pub async fn import_follows(
+    locales: Locales,
+    auth: State<'_, Auth>,
+    follows: State<'_, Follows>,
+) -> Result<Vec<FollowedManga>> {
+    let imported = service::fetch_follows(&auth, &locales).await?;
+    follows.replace(imported.clone())?;
+
+    Ok(imported)
+}
+
+#[tauri::command]
+pub async fn get_follows(follows: State<'_, Follows>) -> Result<Vec<FollowedManga>> {
+    Ok(follows.get())
+}
+
+#[tauri::command]
+pub async fn sync_read_markers(
+    manga_id: &str,
+    auth: State<'_, Auth>,
+    progress: State<'_, ReadProgress>,
+) -> Result<ReadMarkersSync> {
+    Ok(service::sync_read_markers(&auth, &progress, manga_id).await?)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::processing::OutputFormat;
+
+    fn options(profile: Option<Value>, stitch_strips: Option<bool>) -> DownloadOptions {
+        DownloadOptions {
+            profile,
+            stitch_strips,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn applies_profile_arguments_over_saved_settings() {
+        let mut saved = Settings::default();
+        saved.output.format = OutputFormat::Jpeg;
+        saved.output.quality = 70;
+        saved.output.grayscale = true;
+
+        let strip_height = Some(json!({ "stripHeight": 1600 }));
+        let (profile, device) =
+            download_profile(&saved, options(strip_height.clone(), None)).unwrap();
+        assert!(device.is_none());
+        assert_eq!(profile.format, OutputFormat::Jpeg);
+        assert_eq!(profile.quality, 70);
+        assert!(profile.grayscale);
+        assert_eq!(profile.strip_height, Some(1600));
+
+        saved.device = Some("kobo-clara".to_owned());
+        let (profile, device) =
+            download_profile(&saved, options(strip_height.clone(), None)).unwrap();
+        assert_eq!(device.unwrap().id, "kobo-clara");
+        assert_eq!(profile.max_height, Some(1448));
+        assert_eq!(profile.strip_height, Some(1600));
+
+        let invalid = Some(json!({ "quality": "high" }));
+        assert!(download_profile(&saved, options(invalid, None)).is_err());
+    }
+
+    #[test]
+    fn stitches_strips_at_the_screen_height() {
+        let mut saved = Settings {
+            device: Some("kobo-clara".to_owned()),
+            ..Default::default()
+        };
+        let (profile, _) = download_profile(&saved, options(None, Some(true))).unwrap();
+        assert_eq!(profile.strip_height, Some(1448));
+
+        saved.device = None;
+        let (profile, _) = download_profile(&saved, options(None, Some(true))).unwrap();
+        assert_eq!(profile.strip_height, Some(2000));
+
+        saved.output.strip_height = Some(1600);
+        let (profile, _) = download_profile(&saved, options(None, None)).unwrap();
+        assert_eq!(profile.strip_height, Some(1600));
+        let (profile, _) = download_profile(&saved, options(None, Some(false))).unwrap();
+        assert!(profile.strip_height.is_none());
+    }
+}
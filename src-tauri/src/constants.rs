@@ -1,4 +1,23 @@
+use std::time::Duration;
+
 pub const MANGADEX_UPLOADS: &str = "https://uploads.mangadex.org";
 pub const MANGADEX_API: &str = "https://api.mangadex.org";
+pub const MANGADEX_AUTH_URL: &str =
+    "https://auth.mangadex.org/realms/mangadex/protocol/openid-connect/token";
 pub const MANGADEX_REPORT_URL: &str = "https://api.mangadex.network/report";
 pub const MAX_FRAME_RETRIES: u32 = 10;
+pub const MAX_SERVER_ROTATIONS: u32 = 3;
+pub const FRAME_RETRY_DELAY: Duration = Duration::from_millis(500);
+pub const MAX_FRAME_RETRY_DELAY: Duration = Duration::from_secs(10);
+pub const COVERS_PAGE_SIZE: u32 = 100;
+pub const COVER_QUALITY: u8 = 90;
+pub const APP_DIR_NAME: &str = "manga-fetcher";
+pub const USER_AGENT: &str = concat!("manga-fetcher/", env!("CARGO_PKG_VERSION"));
+
+pub const SEARCH_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+pub const MANGA_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+pub const STATISTICS_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+pub const FEED_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+pub const COVERS_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+pub const PAGES_CACHE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+pub const PAGES_CACHE_MAX_BYTES: u64 = 512 * 1024 * 1024;
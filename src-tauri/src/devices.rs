@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+
+use crate::model::{Result, ServiceError};
+use crate::processing::{OutputFormat, OutputProfile};
+
+const E_INK_CONTRAST: f32 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    Cbz,
+    /// Fixed layout EPUB with the Kindle comic metadata
+    Epub,
+    /// Fixed layout EPUB saved as `.kepub.epub` for Kobo readers
+    Kepub,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Cbz => "cbz",
+            ArchiveFormat::Epub => "epub",
+            ArchiveFormat::Kepub => "kepub.epub",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceProfile {
+    pub id: String,
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub grayscale: bool,
+    pub contrast: f32,
+    pub archive: ArchiveFormat,
+    pub right_to_left: bool,
+}
+
+impl DeviceProfile {
+    fn e_ink(id: &str, name: &str, width: u32, height: u32, archive: ArchiveFormat) -> Self {
+        DeviceProfile {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            width,
+            height,
+            grayscale: true,
+            contrast: E_INK_CONTRAST,
+            archive,
+            right_to_left: true,
+        }
+    }
+
+    pub fn output_profile(&self) -> OutputProfile {
+        OutputProfile {
+            format: OutputFormat::Jpeg,
+            max_width: Some(self.width),
+            max_height: Some(self.height),
+            grayscale: self.grayscale,
+            contrast: self.contrast,
+            trim_borders: true,
+            split_spreads: true,
+            right_to_left: self.right_to_left,
+            ..Default::default()
+        }
+    }
+}
+
+pub fn device_profiles() -> Vec<DeviceProfile> {
+    use ArchiveFormat::*;
+
+    vec![
+        DeviceProfile::e_ink("kindle-paperwhite", "Kindle Paperwhite", 1236, 1648, Epub),
+        DeviceProfile::e_ink("kindle-oasis", "Kindle Oasis", 1264, 1680, Epub),
+        DeviceProfile::e_ink("kindle-scribe", "Kindle Scribe", 1860, 2480, Epub),
+        DeviceProfile::e_ink("kobo-clara", "Kobo Clara", 1072, 1448, Kepub),
+        DeviceProfile::e_ink("kobo-libra", "Kobo Libra", 1264, 1680, Kepub),
+        DeviceProfile::e_ink("kobo-sage", "Kobo Sage", 1440, 1920, Kepub),
+        DeviceProfile::e_ink("kobo-elipsa", "Kobo Elipsa", 1404, 1872, Kepub),
+        DeviceProfile::e_ink("generic-cbz", "Generic e-reader (CBZ)", 1072, 1448, Cbz),
+    ]
+}
+
+pub fn find_device_profile(id: &str) -> Result<DeviceProfile> {
+    device_profiles()
+        .into_iter()
+        .find(|profile| profile.id == id)
+        .ok_or_else(|| ServiceError::InvalidArguments(format!("unknown device profile \"{id}\"")))
+}
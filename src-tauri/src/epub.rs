@@ -0,0 +1,288 @@
+use std::fmt::Write as _;
+use std::io::{Cursor, Seek, Write};
+
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::archive::entry_options;
+use crate::devices::DeviceProfile;
+use crate::model::Result;
+use crate::reader::mime_type;
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+// no release date is known when packing, a fixed value keeps re-downloads of a
+// chapter identical
+const MODIFIED: &str = "2000-01-01T00:00:00Z";
+// BCP 47 tag for chapters without a known translation language
+const UNDETERMINED_LANGUAGE: &str = "und";
+
+struct Page {
+    image: String,
+    media_type: &'static str,
+    width: u32,
+    height: u32,
+}
+
+/// Fixed layout EPUB written page by page into an archive.
+pub struct EpubBook {
+    chapter_id: String,
+    title: String,
+    language: String,
+    device: DeviceProfile,
+    pages: Vec<Page>,
+}
+
+impl EpubBook {
+    /// `language` is the chapter's translation language, e.g. `en` or `pt-br`.
+    pub fn new(
+        chapter_id: &str,
+        title: &str,
+        language: Option<&str>,
+        device: &DeviceProfile,
+    ) -> Self {
+        EpubBook {
+            chapter_id: chapter_id.to_owned(),
+            title: title.to_owned(),
+            language: language.unwrap_or(UNDETERMINED_LANGUAGE).to_owned(),
+            device: device.clone(),
+            pages: Vec::new(),
+        }
+    }
+
+    pub fn start<W: Write + Seek>(&self, writer: &mut ZipWriter<W>) -> Result<()> {
+        // the mimetype entry has to come first and stay uncompressed
+        writer.start_file("mimetype", entry_options(CompressionMethod::Stored))?;
+        writer.write_all(b"application/epub+zip")?;
+
+        writer.start_file(
+            "META-INF/container.xml",
+            entry_options(CompressionMethod::Deflated),
+        )?;
+        writer.write_all(CONTAINER_XML.as_bytes())?;
+
+        Ok(())
+    }
+
+    pub fn add_image<W: Write + Seek>(
+        &mut self,
+        writer: &mut ZipWriter<W>,
+        name: &str,
+        data: &[u8],
+    ) -> Result<()> {
+        let extension = name.rsplit_once('.').map_or("jpg", |(_, ext)| ext);
+        let image = format!("{:04}.{extension}", self.pages.len());
+        let (width, height) = image::io::Reader::new(Cursor::new(data))
+            .with_guessed_format()
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok())
+            .unwrap_or((self.device.width, self.device.height));
+
+        writer.start_file(
+            format!("OEBPS/images/{image}"),
+            entry_options(CompressionMethod::Stored),
+        )?;
+        writer.write_all(data)?;
+
+        self.pages.push(Page {
+            media_type: mime_type(&image),
+            image,
+            width,
+            height,
+        });
+
+        Ok(())
+    }
+
+    pub fn finish<W: Write + Seek>(&self, writer: &mut ZipWriter<W>) -> Result<()> {
+        let title = &self.title;
+        let deflated = entry_options(CompressionMethod::Deflated);
+
+        for (index, page) in self.pages.iter().enumerate() {
+            writer.start_file(format!("OEBPS/page-{index:04}.xhtml"), deflated)?;
+            writer.write_all(page_xhtml(title, page).as_bytes())?;
+        }
+
+        writer.start_file("OEBPS/nav.xhtml", deflated)?;
+        writer.write_all(nav_xhtml(title).as_bytes())?;
+
+        writer.start_file("OEBPS/toc.ncx", deflated)?;
+        writer.write_all(toc_ncx(title).as_bytes())?;
+
+        writer.start_file("OEBPS/content.opf", deflated)?;
+        writer.write_all(
+            content_opf(
+                &self.chapter_id,
+                title,
+                &self.language,
+                &self.device,
+                &self.pages,
+            )
+            .as_bytes(),
+        )?;
+
+        Ok(())
+    }
+}
+
+fn content_opf(
+    chapter_id: &str,
+    title: &str,
+    language: &str,
+    device: &DeviceProfile,
+    pages: &[Page],
+) -> String {
+    let title = escape(title);
+    let language = escape(language);
+    let (direction, writing_mode) = if device.right_to_left {
+        ("rtl", "horizontal-rl")
+    } else {
+        ("ltr", "horizontal-lr")
+    };
+
+    let mut manifest = String::new();
+    let mut spine = String::new();
+
+    for (index, page) in pages.iter().enumerate() {
+        let _ = writeln!(
+            manifest,
+            r#"    <item id="image-{index}" href="images/{}" media-type="{}"/>"#,
+            page.image, page.media_type
+        );
+        let _ = writeln!(
+            manifest,
+            r#"    <item id="page-{index}" href="page-{index:04}.xhtml" media-type="application/xhtml+xml"/>"#
+        );
+        let _ = writeln!(spine, r#"    <itemref idref="page-{index}"/>"#);
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package version="3.0" unique-identifier="book-id" xmlns="http://www.idpf.org/2007/opf">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">urn:uuid:{identifier}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:language>{language}</dc:language>
+    <meta property="dcterms:modified">{MODIFIED}</meta>
+    <meta property="rendition:layout">pre-paginated</meta>
+    <meta property="rendition:orientation">portrait</meta>
+    <meta property="rendition:spread">none</meta>
+    <meta name="book-type" content="comic"/>
+    <meta name="fixed-layout" content="true"/>
+    <meta name="original-resolution" content="{width}x{height}"/>
+    <meta name="primary-writing-mode" content="{writing_mode}"/>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+{manifest}  </manifest>
+  <spine toc="ncx" page-progression-direction="{direction}">
+{spine}  </spine>
+</package>
+"#,
+        identifier = escape(chapter_id),
+        width = device.width,
+        height = device.height,
+    )
+}
+
+fn page_xhtml(title: &str, page: &Page) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head>
+  <title>{title}</title>
+  <meta name="viewport" content="width={width}, height={height}"/>
+  <style>body {{ margin: 0; }} img {{ width: 100%; height: 100%; }}</style>
+</head>
+<body>
+  <img src="images/{image}" alt=""/>
+</body>
+</html>
+"#,
+        title = escape(title),
+        width = page.width,
+        height = page.height,
+        image = page.image,
+    )
+}
+
+fn nav_xhtml(title: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>{title}</title></head>
+<body>
+  <nav epub:type="toc">
+    <ol><li><a href="page-0000.xhtml">{title}</a></li></ol>
+  </nav>
+</body>
+</html>
+"#,
+        title = escape(title),
+    )
+}
+
+fn toc_ncx(title: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx version="2005-1" xmlns="http://www.daisy.org/z3986/2005/ncx/">
+  <head/>
+  <docTitle><text>{title}</text></docTitle>
+  <navMap>
+    <navPoint id="start" playOrder="1">
+      <navLabel><text>{title}</text></navLabel>
+      <content src="page-0000.xhtml"/>
+    </navPoint>
+  </navMap>
+</ncx>
+"#,
+        title = escape(title),
+    )
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn identifies_books_by_chapter() {
+        let device = &crate::devices::device_profiles()[0];
+        let chapter_id = "a54c491c-8e4c-4e97-8873-5b79e59da210";
+
+        let opf = content_opf(chapter_id, "Title", "pt-br", device, &[]);
+
+        assert!(opf.contains(&format!("urn:uuid:{chapter_id}")));
+        assert!(opf.contains("<dc:language>pt-br</dc:language>"));
+        assert_eq!(opf, content_opf(chapter_id, "Title", "pt-br", device, &[]));
+    }
+
+    #[test]
+    fn escapes_titles() {
+        assert_eq!(escape("Tom & Jerry <3"), "Tom &amp; Jerry &lt;3");
+    }
+}
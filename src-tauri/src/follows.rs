@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::constants::APP_DIR_NAME;
+use crate::model::{FollowedManga, Result};
+use crate::store::JsonStore;
+use crate::time::unix_now;
+
+const FOLLOWS_FILE: &str = "follows.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FollowsData {
+    imported_at: Option<u64>,
+    manga: Vec<FollowedManga>,
+}
+
+/// Followed manga as last imported from the MangaDex account.
+pub struct Follows {
+    store: JsonStore<FollowsData>,
+}
+
+impl Default for Follows {
+    fn default() -> Self {
+        Follows::load(get_follows_path())
+    }
+}
+
+impl Follows {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        Follows {
+            store: JsonStore::load("follows", path),
+        }
+    }
+
+    pub fn get(&self) -> Vec<FollowedManga> {
+        self.store.lock().manga.clone()
+    }
+
+    pub fn replace(&self, manga: Vec<FollowedManga>) -> Result<()> {
+        let mut data = self.store.lock();
+        data.manga = manga;
+        data.imported_at = Some(unix_now());
+
+        self.store.save(&data)
+    }
+}
+
+fn get_follows_path() -> Option<PathBuf> {
+    tauri::api::path::data_dir().map(|dir| dir.join(APP_DIR_NAME).join(FOLLOWS_FILE))
+}
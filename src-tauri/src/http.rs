@@ -0,0 +1,284 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use reqwest::header::{HeaderMap, HeaderValue, FROM, RETRY_AFTER};
+use reqwest::{Certificate, Client, NoProxy, Proxy, Response, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{APP_DIR_NAME, USER_AGENT};
+use crate::model::{Result, ServiceError};
+
+const KEYRING_PROXY_USER: &str = "proxy";
+const PROXY_SCHEMES: [&str; 4] = ["http", "https", "socks5", "socks5h"];
+const PEM_END: &str = "-----END CERTIFICATE-----";
+
+// every request goes through this client so proxy and certificate changes
+// apply to api calls and image downloads alike, settings that failed to apply
+// are kept as an error so requests never silently bypass the proxy
+static CLIENT: Mutex<Option<std::result::Result<Client, String>>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NetworkSettings {
+    pub proxy: Option<ProxySettings>,
+    /// PEM bundle or DER file with extra trusted root certificates.
+    pub ca_bundle: Option<PathBuf>,
+    /// Replaces the default `manga-fetcher/<version>` agent.
+    pub user_agent: Option<String>,
+    /// Email sent in the `From` header so MangaDex can reach out about traffic.
+    pub contact: Option<String>,
+    /// Send delivery reports for images served by MangaDex@Home.
+    pub report_frames: bool,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        NetworkSettings {
+            proxy: None,
+            ca_bundle: None,
+            user_agent: None,
+            contact: None,
+            report_frames: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxySettings {
+    /// `http://`, `https://` or `socks5://` proxy url.
+    pub url: String,
+    /// Login for authenticating proxies, the password is kept in the OS keyring.
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Hosts reached without the proxy, e.g. `localhost` or `.example.com`.
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+impl NetworkSettings {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(user_agent) = &self.user_agent {
+            if user_agent.trim().is_empty() {
+                return Err(ServiceError::InvalidArguments(
+                    "user agent must not be empty".to_owned(),
+                ));
+            }
+        }
+        for value in self.user_agent.iter().chain(&self.contact) {
+            header_value(value)?;
+        }
+
+        if let Some(proxy) = &self.proxy {
+            let url = Url::parse(&proxy.url)
+                .map_err(|e| ServiceError::InvalidArguments(format!("invalid proxy url: {e}")))?;
+
+            if !PROXY_SCHEMES.contains(&url.scheme()) {
+                return Err(ServiceError::InvalidArguments(format!(
+                    "unsupported proxy scheme \"{}\"",
+                    url.scheme()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn client() -> Result<Client> {
+    lock()
+        .get_or_insert_with(|| build_client(&NetworkSettings::default()).map_err(|e| e.to_string()))
+        .clone()
+        .map_err(ServiceError::NetworkSettings)
+}
+
+pub fn configure(settings: &NetworkSettings) -> Result<()> {
+    match build_client(settings) {
+        Ok(client) => {
+            set_client(client);
+            Ok(())
+        }
+        Err(e) => {
+            *lock() = Some(Err(e.to_string()));
+            Err(e)
+        }
+    }
+}
+
+pub fn set_client(client: Client) {
+    *lock() = Some(Ok(client));
+}
+
+pub fn build_client(settings: &NetworkSettings) -> Result<Client> {
+    let mut headers = HeaderMap::new();
+    if let Some(contact) = &settings.contact {
+        headers.insert(FROM, header_value(contact)?);
+    }
+
+    let user_agent = settings.user_agent.as_deref().unwrap_or(USER_AGENT);
+    let mut builder = Client::builder()
+        .user_agent(header_value(user_agent)?)
+        .default_headers(headers);
+
+    if let Some(proxy) = &settings.proxy {
+        builder = builder.proxy(build_proxy(proxy)?);
+    }
+
+    if let Some(path) = &settings.ca_bundle {
+        for certificate in read_certificates(path)? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    Ok(builder.build()?)
+}
+
+pub fn set_proxy_password(password: Option<&str>) -> Result<()> {
+    let entry = proxy_password_entry()?;
+
+    match password {
+        Some(password) => entry.set_password(password)?,
+        None => match entry.delete_password() {
+            Ok(_) | Err(keyring::Error::NoEntry) => {}
+            Err(e) => return Err(e.into()),
+        },
+    }
+
+    Ok(())
+}
+
+/// Like `Response::error_for_status`, but rate limits keep the delay the
+/// server asked for.
+pub fn check_status(response: Response) -> Result<Response> {
+    if response.status() == StatusCode::TOO_MANY_REQUESTS {
+        return Err(ServiceError::RateLimited {
+            retry_after: retry_after(&response),
+        });
+    }
+
+    Ok(response.error_for_status()?)
+}
+
+/// Seconds from a `Retry-After` header, HTTP dates are not supported.
+pub fn retry_after(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
+fn build_proxy(settings: &ProxySettings) -> Result<Proxy> {
+    let mut proxy = Proxy::all(&settings.url)?;
+
+    if let Some(username) = &settings.username {
+        let password = load_proxy_password()?.unwrap_or_default();
+        proxy = proxy.basic_auth(username, &password);
+    }
+
+    if !settings.no_proxy.is_empty() {
+        proxy = proxy.no_proxy(NoProxy::from_string(&settings.no_proxy.join(",")));
+    }
+
+    Ok(proxy)
+}
+
+// control characters are rejected before trimming, so a value ending in a
+// newline isn't accepted here and then sent differently than it was saved
+fn header_value(value: &str) -> Result<HeaderValue> {
+    let invalid = || ServiceError::InvalidArguments(format!("invalid header value \"{value}\""));
+
+    if value.chars().any(char::is_control) {
+        return Err(invalid());
+    }
+
+    HeaderValue::from_str(value.trim()).map_err(|_| invalid())
+}
+
+fn read_certificates(path: &Path) -> Result<Vec<Certificate>> {
+    let data = fs::read(path)?;
+
+    let text = match std::str::from_utf8(&data) {
+        Ok(text) if text.contains(PEM_END) => text,
+        _ => return Ok(vec![Certificate::from_der(&data)?]),
+    };
+
+    // a bundle holds several concatenated certificates, each is parsed on its own
+    text.split_inclusive(PEM_END)
+        .filter(|block| block.contains(PEM_END))
+        .map(|block| Ok(Certificate::from_pem(block.trim_start().as_bytes())?))
+        .collect()
+}
+
+fn proxy_password_entry() -> Result<keyring::Entry> {
+    Ok(keyring::Entry::new(APP_DIR_NAME, KEYRING_PROXY_USER)?)
+}
+
+fn load_proxy_password() -> Result<Option<String>> {
+    match proxy_password_entry()?.get_password() {
+        Ok(password) => Ok(Some(password)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn lock() -> MutexGuard<'static, Option<std::result::Result<Client, String>>> {
+    CLIENT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validates_proxy_urls() {
+        let with_proxy = |url: &str| NetworkSettings {
+            proxy: Some(ProxySettings {
+                url: url.to_owned(),
+                username: None,
+                no_proxy: vec!["localhost".to_owned()],
+            }),
+            ..Default::default()
+        };
+
+        assert!(with_proxy("http://127.0.0.1:8080").validate().is_ok());
+        assert!(with_proxy("socks5://127.0.0.1:1080").validate().is_ok());
+        assert!(with_proxy("ftp://127.0.0.1").validate().is_err());
+        assert!(with_proxy("not a url").validate().is_err());
+        assert!(build_client(&with_proxy("http://127.0.0.1:8080")).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let settings = NetworkSettings {
+            user_agent: Some(" reader/1.0 ".to_owned()),
+            ..Default::default()
+        };
+        assert!(settings.validate().is_ok());
+
+        let settings = NetworkSettings {
+            user_agent: Some("reader/1.0\n".to_owned()),
+            ..Default::default()
+        };
+        assert!(settings.validate().is_err());
+
+        let settings = NetworkSettings {
+            contact: Some("me@example.com\r\nX-Injected: 1".to_owned()),
+            ..Default::default()
+        };
+        assert!(settings.validate().is_err());
+        assert!(build_client(&settings).is_err());
+    }
+
+    #[test]
+    fn checks_certificate_bundles_when_building() {
+        let settings = NetworkSettings {
+            ca_bundle: Some(PathBuf::from("/nonexistent/bundle.pem")),
+            ..Default::default()
+        };
+
+        assert!(settings.validate().is_ok());
+        assert!(build_client(&settings).is_err());
+    }
+}
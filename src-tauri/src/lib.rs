@@ -1,4 +1,22 @@
+pub mod archive;
+pub mod auth;
+pub mod cache;
+pub mod collision;
 pub mod commands;
 pub mod constants;
+pub mod devices;
+pub mod epub;
+pub mod follows;
+pub mod http;
+pub mod library;
+pub mod limits;
 pub mod model;
+pub mod online;
+pub mod processing;
+pub mod progress;
+pub mod reader;
+pub mod reports;
 pub mod service;
+pub mod settings;
+pub mod store;
+pub mod time;
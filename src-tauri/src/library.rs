@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::collision::ReleaseInfo;
+use crate::constants::APP_DIR_NAME;
+use crate::model::Result;
+use crate::store::JsonStore;
+use crate::time::unix_now;
+
+const LIBRARY_FILE: &str = "library.json";
+const ARCHIVE_EXTENSIONS: [&str; 3] = ["cbz", "zip", "epub"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryEntry {
+    pub manga_id: String,
+    pub chapter_id: String,
+    pub path: PathBuf,
+    pub added_at: u64,
+}
+
+impl LibraryEntry {
+    pub fn new(manga_id: &str, chapter_id: &str, path: PathBuf) -> Self {
+        LibraryEntry {
+            manga_id: manga_id.to_owned(),
+            chapter_id: chapter_id.to_owned(),
+            path,
+            added_at: unix_now(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanReport {
+    pub imported: usize,
+    pub known: usize,
+    pub removed: usize,
+    pub unmatched: Vec<PathBuf>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LibraryData {
+    chapters: HashMap<String, LibraryEntry>,
+}
+
+/// Chapters available on disk, either downloaded or imported by a scan.
+pub struct Library {
+    store: JsonStore<LibraryData>,
+}
+
+impl Default for Library {
+    fn default() -> Self {
+        Library::load(get_library_path())
+    }
+}
+
+impl Library {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        Library {
+            store: JsonStore::load("library", path),
+        }
+    }
+
+    pub fn contains(&self, chapter_id: &str) -> bool {
+        self.store.lock().chapters.contains_key(chapter_id)
+    }
+
+    pub fn manga(&self, manga_id: &str) -> Vec<LibraryEntry> {
+        self.store
+            .lock()
+            .chapters
+            .values()
+            .filter(|entry| entry.manga_id == manga_id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn register(&self, entries: Vec<LibraryEntry>) -> Result<()> {
+        let mut data = self.store.lock();
+
+        for entry in entries {
+            data.chapters.insert(entry.chapter_id.to_owned(), entry);
+        }
+
+        self.store.save(&data)
+    }
+
+    /// Forgets chapters whose archive is gone, returns how many were removed.
+    pub fn prune(&self) -> Result<usize> {
+        let mut data = self.store.lock();
+        let before = data.chapters.len();
+        data.chapters.retain(|_, entry| entry.path.exists());
+
+        let removed = before - data.chapters.len();
+        if removed > 0 {
+            self.store.save(&data)?;
+        }
+
+        Ok(removed)
+    }
+}
+
+/// What could be learned about a chapter archive without going online.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ArchiveInfo {
+    pub path: PathBuf,
+    pub series: Option<String>,
+    pub volume: Option<String>,
+    pub chapter: Option<String>,
+    pub manga_id: Option<String>,
+    pub chapter_id: Option<String>,
+}
+
+pub fn find_archives(folders: &[PathBuf]) -> Vec<PathBuf> {
+    let mut archives = Vec::new();
+    let mut pending: Vec<PathBuf> = folders.to_vec();
+
+    while let Some(dir) = pending.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("failed to read {}: {e}", dir.display());
+                continue;
+            }
+        };
+
+        for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            if path.is_dir() {
+                pending.push(path);
+            } else if is_archive(&path) {
+                archives.push(path);
+            }
+        }
+    }
+
+    archives.sort();
+    archives
+}
+
+pub fn read_archive_info(path: &Path) -> ArchiveInfo {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut info = parse_file_name(&file_name);
+    info.path = path.to_owned();
+
+    if let Some(release) = ReleaseInfo::read(path) {
+        info.manga_id = release.manga_id;
+        info.chapter_id = Some(release.chapter_id);
+    }
+
+    if let Some(comic_info) = read_comic_info(path) {
+        let tag = |name| xml_tag(&comic_info, name);
+
+        info.series = tag("Series").or(info.series);
+        info.volume = tag("Volume").map(|v| normalize_number(&v)).or(info.volume);
+        info.chapter = tag("Number").map(|n| normalize_number(&n)).or(info.chapter);
+
+        if let Some(web) = tag("Web") {
+            info.manga_id = info.manga_id.or_else(|| mangadex_id(&web, "title"));
+            info.chapter_id = info.chapter_id.or_else(|| mangadex_id(&web, "chapter"));
+        }
+    }
+
+    // our own downloads and most tools keep chapters in a folder per series
+    if info.series.is_none() {
+        info.series = path
+            .parent()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().into_owned());
+    }
+
+    info
+}
+
+fn read_comic_info(path: &Path) -> Option<String> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(path).ok()?).ok()?;
+    let name = archive
+        .file_names()
+        .find(|name| name.eq_ignore_ascii_case("ComicInfo.xml"))?
+        .to_owned();
+
+    let mut xml = String::new();
+    archive.by_name(&name).ok()?.read_to_string(&mut xml).ok()?;
+
+    Some(xml)
+}
+
+fn xml_tag(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("<{tag}>"))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{tag}>"))?;
+    let value = xml[start..end]
+        .trim()
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+
+    Some(value).filter(|value| !value.is_empty())
+}
+
+fn mangadex_id(urls: &str, kind: &str) -> Option<String> {
+    let marker = format!("mangadex.org/{kind}/");
+    let start = urls.find(&marker)? + marker.len();
+    let id: String = urls[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_hexdigit() || *c == '-')
+        .collect();
+
+    Some(id).filter(|id| id.len() == 36)
+}
+
+/// Understands names like `Series Chapter 12 - Title`, `Series v02 c012 [Group]`
+/// or `Series Vol. 2 Ch. 12.5`.
+fn parse_file_name(file_name: &str) -> ArchiveInfo {
+    let stem = strip_archive_extension(file_name);
+
+    let mut cleaned = String::with_capacity(stem.len());
+    let mut depth: usize = 0;
+    for c in stem.chars() {
+        match c {
+            '[' | '{' => depth += 1,
+            // a stray closing bracket must not hide the rest of the name
+            ']' | '}' => depth = depth.saturating_sub(1),
+            '(' | ')' | '_' => cleaned.push(' '),
+            c if depth == 0 => cleaned.push(c),
+            _ => {}
+        }
+    }
+
+    let words: Vec<&str> = cleaned.split_whitespace().collect();
+    let mut info = ArchiveInfo::default();
+    let mut series_end = None;
+    let mut index = 0;
+
+    while index < words.len() {
+        let word = words[index].to_lowercase();
+        let next = words.get(index + 1).and_then(|next| parse_number(next));
+
+        let (marker, inline) = split_marker(&word);
+        let consumed = if inline.is_some() || next.is_none() {
+            1
+        } else {
+            2
+        };
+        let value = inline.or(next);
+
+        match marker {
+            Some(Marker::Chapter) if value.is_some() && info.chapter.is_none() => {
+                info.chapter = value;
+            }
+            Some(Marker::Volume) if value.is_some() && info.volume.is_none() => {
+                info.volume = value;
+            }
+            _ => {
+                index += 1;
+                continue;
+            }
+        }
+
+        series_end = series_end.or(Some(index));
+        index += consumed;
+    }
+
+    // a bare trailing number is the chapter, e.g. `Series 012`
+    if info.chapter.is_none() && series_end.is_none() && words.len() > 1 {
+        if let Some(number) = words.last().and_then(|word| parse_number(word)) {
+            info.chapter = Some(number);
+            series_end = Some(words.len() - 1);
+        }
+    }
+
+    let series = words[..series_end.unwrap_or(words.len())]
+        .join(" ")
+        .trim_end_matches(|c: char| c == '-' || c.is_whitespace())
+        .to_owned();
+    info.series = Some(series).filter(|series| !series.is_empty() && series_end.is_some());
+
+    info
+}
+
+enum Marker {
+    Chapter,
+    Volume,
+}
+
+fn split_marker(word: &str) -> (Option<Marker>, Option<String>) {
+    let markers = [
+        ("chapter", Marker::Chapter),
+        ("ch.", Marker::Chapter),
+        ("ch", Marker::Chapter),
+        ("c", Marker::Chapter),
+        ("#", Marker::Chapter),
+        ("volume", Marker::Volume),
+        ("vol.", Marker::Volume),
+        ("vol", Marker::Volume),
+        ("v", Marker::Volume),
+    ];
+
+    for (prefix, marker) in markers {
+        if let Some(rest) = word.strip_prefix(prefix) {
+            if rest.is_empty() {
+                // single letters are only markers when glued to a number
+                return match prefix.len() {
+                    1 => (None, None),
+                    _ => (Some(marker), None),
+                };
+            }
+            if let Some(number) = parse_number(rest) {
+                return (Some(marker), Some(number));
+            }
+        }
+    }
+
+    (None, None)
+}
+
+fn parse_number(word: &str) -> Option<String> {
+    let word = word.trim_end_matches([',', ':']);
+    let (whole, fraction) = word.split_once('.').unwrap_or((word, ""));
+
+    let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if whole.is_empty() || !is_digits(whole) || !is_digits(fraction) {
+        return None;
+    }
+
+    Some(normalize_number(word))
+}
+
+/// `012` and `12.50` are the same chapter MangaDex calls `12` and `12.5`.
+pub fn normalize_number(number: &str) -> String {
+    let number = number.trim();
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    let whole = whole.trim_start_matches('0');
+    let whole = if whole.is_empty() { "0" } else { whole };
+    let fraction = fraction.trim_end_matches('0');
+
+    if fraction.is_empty() {
+        whole.to_owned()
+    } else {
+        format!("{whole}.{fraction}")
+    }
+}
+
+fn strip_archive_extension(file_name: &str) -> &str {
+    let file_name = file_name.strip_suffix(".kepub.epub").unwrap_or(file_name);
+
+    match file_name.rsplit_once('.') {
+        Some((stem, ext)) if ARCHIVE_EXTENSIONS.contains(&ext.to_lowercase().as_str()) => stem,
+        _ => file_name,
+    }
+}
+
+fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| {
+            ARCHIVE_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+        })
+}
+
+fn get_library_path() -> Option<PathBuf> {
+    tauri::api::path::data_dir().map(|dir| dir.join(APP_DIR_NAME).join(LIBRARY_FILE))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parsed(file_name: &str) -> (Option<String>, Option<String>, Option<String>) {
+        let info = parse_file_name(file_name);
+        (info.series, info.volume, info.chapter)
+    }
+
+    fn some(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn parses_file_name_conventions() {
+        assert_eq!(
+            parsed("Frieren Chapter 12 - The Hero.cbz"),
+            (some("Frieren"), None, some("12"))
+        );
+        assert_eq!(
+            parsed("Frieren - v02 c012.5 [Group].cbz"),
+            (some("Frieren"), some("2"), some("12.5"))
+        );
+        assert_eq!(
+            parsed("Frieren Vol. 3 Ch. 20.kepub.epub"),
+            (some("Frieren"), some("3"), some("20"))
+        );
+        assert_eq!(
+            parsed("Frieren 007.zip"),
+            (some("Frieren"), None, some("7"))
+        );
+        assert_eq!(
+            parsed("Title] v01 c001.cbz"),
+            (some("Title"), some("1"), some("1"))
+        );
+        assert_eq!(parsed("cover.cbz"), (None, None, None));
+    }
+
+    #[test]
+    fn reads_comic_info_fields() {
+        let xml = "<ComicInfo><Series>Tom &amp; Jerry</Series><Number>03</Number>\
+            <Web>https://mangadex.org/chapter/0a1b2c3d-0a1b-0a1b-0a1b-0a1b2c3d4e5f</Web></ComicInfo>";
+
+        assert_eq!(xml_tag(xml, "Series"), some("Tom & Jerry"));
+        assert_eq!(xml_tag(xml, "Volume"), None);
+        assert_eq!(
+            mangadex_id(&xml_tag(xml, "Web").unwrap(), "chapter"),
+            some("0a1b2c3d-0a1b-0a1b-0a1b-0a1b2c3d4e5f")
+        );
+    }
+
+    #[test]
+    fn normalizes_numbers() {
+        assert_eq!(normalize_number("012"), "12");
+        assert_eq!(normalize_number("12.50"), "12.5");
+        assert_eq!(normalize_number("0"), "0");
+    }
+}
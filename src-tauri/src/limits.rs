@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use chrono::{Local, Timelike};
+use futures::future;
+use log::info;
+use reqwest::{Response, Url};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+
+use crate::model::{Result, ServiceError};
+
+// how often a paused download looks at the schedule again
+const SCHEDULE_POLL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ConcurrencySettings {
+    /// Chapters downloaded at the same time.
+    pub chapters: usize,
+    /// Image requests in flight across all chapters.
+    pub frame_requests: usize,
+    /// Image requests in flight to a single image server.
+    pub per_host: usize,
+}
+
+impl Default for ConcurrencySettings {
+    fn default() -> Self {
+        ConcurrencySettings {
+            chapters: 4,
+            frame_requests: 30,
+            per_host: 10,
+        }
+    }
+}
+
+impl ConcurrencySettings {
+    fn clamped(self) -> Self {
+        ConcurrencySettings {
+            chapters: self.chapters.max(1),
+            frame_requests: self.frame_requests.max(1),
+            per_host: self.per_host.max(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScheduleSettings {
+    /// Bandwidth shared by all image downloads, unlimited when unset.
+    pub max_bytes_per_sec: Option<u64>,
+    /// Local times of day queued chapters may start in, any time when empty.
+    pub windows: Vec<DownloadWindow>,
+}
+
+impl ScheduleSettings {
+    fn allows(&self, now: TimeOfDay) -> bool {
+        self.windows.is_empty() || self.windows.iter().any(|window| window.contains(now))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DownloadWindow {
+    pub start: TimeOfDay,
+    pub end: TimeOfDay,
+}
+
+impl DownloadWindow {
+    // windows ending before they start run past midnight
+    fn contains(&self, time: TimeOfDay) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// Minutes since midnight, written as `HH:MM`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay(u16);
+
+impl TimeOfDay {
+    fn now() -> Self {
+        let now = Local::now();
+        TimeOfDay((now.hour() * 60 + now.minute()) as u16)
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = ServiceError;
+
+    fn try_from(value: String) -> Result<Self> {
+        let invalid = || ServiceError::InvalidArguments(format!("invalid time of day \"{value}\""));
+
+        let (hours, minutes) = value.split_once(':').ok_or_else(invalid)?;
+        let hours: u16 = hours.trim().parse().map_err(|_| invalid())?;
+        let minutes: u16 = minutes.trim().parse().map_err(|_| invalid())?;
+
+        if hours > 23 || minutes > 59 {
+            return Err(invalid());
+        }
+
+        Ok(TimeOfDay(hours * 60 + minutes))
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> Self {
+        time.to_string()
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+/// Caps image requests of all downloads, globally and per host, and paces
+/// them to the configured bandwidth and download windows.
+pub struct RequestLimiter {
+    settings: Mutex<ConcurrencySettings>,
+    global: Arc<Limit>,
+    hosts: Mutex<HashMap<String, Arc<Limit>>>,
+    schedule: Mutex<ScheduleSettings>,
+    next_slot: Mutex<Instant>,
+    cancellations: AtomicU64,
+    cancelled: Notify,
+}
+
+/// Ties a download to the cancellations that happened before it started.
+#[derive(Debug, Clone, Copy)]
+pub struct DownloadToken(u64);
+
+pub struct RequestPermit {
+    _host: LimitPermit,
+    _global: LimitPermit,
+}
+
+// a semaphore that can shrink while its permits are out, the permits it owes
+// are taken from the free ones first and then from the ones given back
+struct Limit {
+    semaphore: Arc<Semaphore>,
+    owed: Mutex<usize>,
+}
+
+struct LimitPermit {
+    limit: Arc<Limit>,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Limit {
+    fn new(size: usize) -> Arc<Limit> {
+        Arc::new(Limit {
+            semaphore: Arc::new(Semaphore::new(size)),
+            owed: Mutex::new(0),
+        })
+    }
+
+    fn resize(&self, from: usize, to: usize) {
+        let mut owed = lock(&self.owed);
+
+        if to >= from {
+            let repaid = (*owed).min(to - from);
+            *owed -= repaid;
+            self.semaphore.add_permits(to - from - repaid);
+            return;
+        }
+
+        *owed += from - to;
+        while *owed > 0 {
+            match self.semaphore.try_acquire() {
+                Ok(permit) => permit.forget(),
+                Err(_) => break,
+            }
+            *owed -= 1;
+        }
+    }
+
+    async fn acquire(self: &Arc<Self>) -> LimitPermit {
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("limiter semaphores are never closed");
+
+        LimitPermit {
+            limit: self.clone(),
+            permit: Some(permit),
+        }
+    }
+}
+
+impl Drop for LimitPermit {
+    fn drop(&mut self) {
+        let mut owed = lock(&self.limit.owed);
+
+        if let Some(permit) = self.permit.take() {
+            if *owed > 0 {
+                *owed -= 1;
+                permit.forget();
+            }
+        }
+    }
+}
+
+impl Default for RequestLimiter {
+    fn default() -> Self {
+        RequestLimiter::new(ConcurrencySettings::default(), ScheduleSettings::default())
+    }
+}
+
+impl RequestLimiter {
+    pub fn new(settings: ConcurrencySettings, schedule: ScheduleSettings) -> Self {
+        let settings = settings.clamped();
+
+        RequestLimiter {
+            global: Limit::new(settings.frame_requests),
+            settings: Mutex::new(settings),
+            hosts: Mutex::default(),
+            schedule: Mutex::new(schedule),
+            next_slot: Mutex::new(Instant::now()),
+            cancellations: AtomicU64::new(0),
+            cancelled: Notify::new(),
+        }
+    }
+
+    pub fn settings(&self) -> ConcurrencySettings {
+        lock(&self.settings).clone()
+    }
+
+    // the limits are resized in place, so requests already running count
+    // against the new ones and lowering a limit never lets more through
+    pub fn configure(&self, settings: ConcurrencySettings) {
+        let settings = settings.clamped();
+        let mut current = lock(&self.settings);
+
+        self.global
+            .resize(current.frame_requests, settings.frame_requests);
+        for host in lock(&self.hosts).values() {
+            host.resize(current.per_host, settings.per_host);
+        }
+
+        *current = settings;
+    }
+
+    pub async fn acquire(&self, url: &str) -> RequestPermit {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned))
+            .unwrap_or_default();
+
+        let host_limit = {
+            // the settings lock keeps `configure` from resizing in between
+            let settings = lock(&self.settings);
+            lock(&self.hosts)
+                .entry(host)
+                .or_insert_with(|| Limit::new(settings.per_host))
+                .clone()
+        };
+
+        // waiting for a busy host first keeps it from holding global permits
+        RequestPermit {
+            _host: host_limit.acquire().await,
+            _global: self.global.acquire().await,
+        }
+    }
+}
+
+impl RequestLimiter {
+    pub fn schedule(&self) -> ScheduleSettings {
+        lock(&self.schedule).clone()
+    }
+
+    pub fn set_schedule(&self, schedule: ScheduleSettings) {
+        *lock(&self.schedule) = schedule;
+    }
+
+    pub fn download_token(&self) -> DownloadToken {
+        DownloadToken(self.cancellations.load(Ordering::SeqCst))
+    }
+
+    /// Cancels every download started so far, later ones are not affected.
+    pub fn cancel_downloads(&self) {
+        self.cancellations.fetch_add(1, Ordering::SeqCst);
+        self.cancelled.notify_waiters();
+    }
+
+    pub fn check_cancelled(&self, token: DownloadToken) -> Result<()> {
+        if self.cancellations.load(Ordering::SeqCst) != token.0 {
+            return Err(ServiceError::Cancelled);
+        }
+
+        Ok(())
+    }
+
+    /// Waits until the schedule allows starting another chapter or frame
+    /// request, or fails once the download is cancelled.
+    pub async fn wait_for_window(&self, token: DownloadToken) -> Result<()> {
+        let mut announced = false;
+
+        loop {
+            self.check_cancelled(token)?;
+            if lock(&self.schedule).allows(TimeOfDay::now()) {
+                return Ok(());
+            }
+
+            if !announced {
+                info!("waiting for the next download window");
+                announced = true;
+            }
+            let poll = Box::pin(tokio::time::sleep(SCHEDULE_POLL));
+            future::select(poll, Box::pin(self.cancelled.notified())).await;
+        }
+    }
+
+    /// Reads a response body at no more than the configured bandwidth.
+    pub async fn read_body(&self, mut response: Response) -> Result<Bytes> {
+        let mut body = BytesMut::new();
+
+        while let Some(chunk) = response.chunk().await? {
+            let delay = self.reserve(chunk.len());
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(body.freeze())
+    }
+
+    // every chunk books the time it takes at the capped rate, so concurrent
+    // downloads queue up behind each other instead of each getting the full rate
+    fn reserve(&self, bytes: usize) -> Duration {
+        let rate = match lock(&self.schedule).max_bytes_per_sec {
+            Some(rate) if rate > 0 => rate,
+            _ => return Duration::ZERO,
+        };
+
+        let now = Instant::now();
+        let mut next_slot = lock(&self.next_slot);
+        let start = (*next_slot).max(now);
+        *next_slot = start + Duration::from_secs_f64(bytes as f64 / rate as f64);
+
+        start - now
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod test {
+    use futures::FutureExt;
+
+    use super::*;
+
+    #[test]
+    fn limits_requests_per_host_and_globally() {
+        let limiter = RequestLimiter::default();
+        limiter.configure(ConcurrencySettings {
+            frame_requests: 2,
+            per_host: 1,
+            ..Default::default()
+        });
+
+        let first = limiter.acquire("https://a.example/1.png").now_or_never();
+        let same_host = limiter.acquire("https://a.example/2.png").now_or_never();
+        let other_host = limiter.acquire("https://b.example/1.png").now_or_never();
+        let over_global = limiter.acquire("https://c.example/1.png").now_or_never();
+
+        assert!(first.is_some());
+        assert!(same_host.is_none());
+        assert!(other_host.is_some());
+        assert!(over_global.is_none());
+    }
+
+    #[test]
+    fn lowered_limits_count_running_requests() {
+        let limiter = RequestLimiter::default();
+        limiter.configure(ConcurrencySettings {
+            frame_requests: 3,
+            ..Default::default()
+        });
+        let acquire = |host: &str| limiter.acquire(host).now_or_never();
+
+        let running: Vec<_> = [
+            "https://a.example",
+            "https://b.example",
+            "https://c.example",
+        ]
+        .iter()
+        .map(|host| acquire(host).unwrap())
+        .collect();
+        limiter.configure(ConcurrencySettings {
+            frame_requests: 1,
+            ..Default::default()
+        });
+
+        let mut running = running.into_iter();
+        drop(running.next());
+        assert!(acquire("https://d.example").is_none());
+        drop(running.next());
+        assert!(acquire("https://d.example").is_none());
+        drop(running.next());
+        assert!(acquire("https://d.example").is_some());
+
+        limiter.configure(ConcurrencySettings {
+            frame_requests: 2,
+            ..Default::default()
+        });
+        let first = acquire("https://a.example");
+        let second = acquire("https://b.example");
+        assert!(first.is_some() && second.is_some());
+        assert!(acquire("https://c.example").is_none());
+    }
+
+    #[test]
+    fn windows_can_span_midnight() {
+        let time = |value: &str| TimeOfDay::try_from(value.to_string()).unwrap();
+        let schedule = ScheduleSettings {
+            windows: vec![DownloadWindow {
+                start: time("22:30"),
+                end: time("06:00"),
+            }],
+            ..Default::default()
+        };
+
+        assert!(schedule.allows(time("23:59")));
+        assert!(schedule.allows(time("00:10")));
+        assert!(!schedule.allows(time("06:00")));
+        assert!(!schedule.allows(time("12:00")));
+        assert!(TimeOfDay::try_from("24:00".to_string()).is_err());
+        assert_eq!(time("7:05").to_string(), "07:05");
+    }
+
+    #[test]
+    fn bandwidth_is_shared_between_reads() {
+        let limiter = RequestLimiter::default();
+        limiter.set_schedule(ScheduleSettings {
+            max_bytes_per_sec: Some(1000),
+            ..Default::default()
+        });
+
+        let first = limiter.reserve(500);
+        let second = limiter.reserve(500);
+
+        assert!(first < Duration::from_millis(10));
+        assert!(second > Duration::from_millis(400));
+    }
+
+    #[test]
+    fn cancels_running_downloads_only() {
+        let limiter = RequestLimiter::default();
+        let running = limiter.download_token();
+
+        limiter.cancel_downloads();
+        let started_later = limiter.download_token();
+
+        assert!(matches!(
+            limiter.wait_for_window(running).now_or_never(),
+            Some(Err(ServiceError::Cancelled))
+        ));
+        assert!(matches!(
+            limiter.wait_for_window(started_later).now_or_never(),
+            Some(Ok(()))
+        ));
+    }
+}
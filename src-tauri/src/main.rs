@@ -3,16 +3,42 @@
     windows_subsystem = "windows"
 )]
 
-use tauri::Menu;
+use log::error;
+use tauri::api::dialog;
+use tauri::{Manager, Menu};
 use tauri_plugin_log::{Builder, LogTarget};
 
+use app::auth::Auth;
 use app::commands;
+use app::follows::Follows;
+use app::http;
+use app::library::Library;
+use app::limits::RequestLimiter;
+use app::online::{self, OnlineReader, CHAPTER_PROTOCOL};
+use app::progress::ReadProgress;
+use app::reader::{self, Reader, READER_PROTOCOL};
+use app::reports;
+use app::settings::SettingsStore;
 
 fn main() {
     let menu = Menu::os_default("Manga Fetcher");
 
+    let settings = SettingsStore::default();
+    let saved = settings.get();
+    let network = saved.network.clone();
+
     tauri::Builder::default()
         .menu(menu)
+        .manage(Reader::default())
+        .manage(OnlineReader::default())
+        .manage(ReadProgress::default())
+        .manage(Auth::default())
+        .manage(Library::default())
+        .manage(Follows::default())
+        .manage(RequestLimiter::new(saved.concurrency, saved.schedule))
+        .manage(settings)
+        .register_uri_scheme_protocol(READER_PROTOCOL, reader::handle_protocol)
+        .register_uri_scheme_protocol(CHAPTER_PROTOCOL, online::handle_protocol)
         .plugin(
             Builder::new()
                 .targets([
@@ -23,12 +49,55 @@ fn main() {
                 ])
                 .build(),
         )
+        .setup(move |app| {
+            reports::set_enabled(network.report_frames);
+            // requests keep failing until the settings are fixed rather than
+            // going out without the configured proxy
+            if let Err(e) = http::configure(&network) {
+                error!("failed to apply network settings: {e}");
+                dialog::message(
+                    app.get_window("main").as_ref(),
+                    "Network settings",
+                    format!(
+                        "The network settings could not be applied, nothing is \
+                         downloaded until they are fixed.\n\n{e}"
+                    ),
+                );
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::search,
             commands::get_manga,
             commands::get_chapters,
             commands::download,
+            commands::cancel_downloads,
+            commands::get_device_profiles,
+            commands::get_settings,
+            commands::update_settings,
+            commands::set_proxy_password,
+            commands::scan_library,
+            commands::get_library,
             commands::aggregate,
+            commands::clear_cache,
+            commands::open_archive,
+            commands::get_archive_pages,
+            commands::prefetch_pages,
+            commands::close_archive,
+            commands::open_online_chapter,
+            commands::load_online_page,
+            commands::close_online_chapter,
+            commands::get_read_progress,
+            commands::mark_chapters,
+            commands::set_last_page,
+            commands::next_unread_chapter,
+            commands::unread_chapters,
+            commands::login,
+            commands::logout,
+            commands::auth_status,
+            commands::import_follows,
+            commands::get_follows,
+            commands::sync_read_markers,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,3 +1,4 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
@@ -14,8 +15,42 @@ pub struct VolumeAggregate {
     pub chapters: HashMap<String, ChapterAggregate>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ChapterAggregate {
     pub id: String,
     pub chapter: String,
+    #[serde(default)]
+    pub others: Vec<String>,
+}
+
+impl AggregateResponse {
+    pub fn ordered_chapters(&self) -> Vec<&ChapterAggregate> {
+        let mut volumes: Vec<&VolumeAggregate> = self.volumes.values().collect();
+        volumes.sort_by(|a, b| compare_numbers(&a.volume, &b.volume));
+
+        volumes
+            .into_iter()
+            .flat_map(|volume| {
+                let mut chapters: Vec<&ChapterAggregate> = volume.chapters.values().collect();
+                chapters.sort_by(|a, b| compare_numbers(&a.chapter, &b.chapter));
+                chapters
+            })
+            .collect()
+    }
+}
+
+impl ChapterAggregate {
+    pub fn ids(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.id).chain(self.others.iter())
+    }
+}
+
+// MangaDex uses "none" for chapters without volume or number, those go last
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
 }
@@ -10,6 +10,6 @@ pub struct AtHomeResponse {
 #[derive(Debug, Deserialize)]
 pub struct ChapterData {
     pub hash: String,
-    #[serde(rename = "dataSaver")]
+    #[serde(rename = "dataSaver", default)]
     pub data_saver: Vec<String>,
 }
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::MangaView;
+
+#[derive(Debug, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_in: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenError {
+    pub error: String,
+    pub error_description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredCredentials {
+    pub username: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthStatus {
+    pub logged_in: bool,
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReadMarkersResponse {
+    pub data: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadMarkersUpdate {
+    pub chapter_ids_read: Vec<String>,
+    pub chapter_ids_unread: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReadingStatusResponse {
+    pub statuses: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FollowedManga {
+    pub view: MangaView,
+    pub reading_status: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadMarkersSync {
+    pub pulled: usize,
+    pub pushed: usize,
+}
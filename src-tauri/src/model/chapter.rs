@@ -2,12 +2,22 @@ use serde::{Deserialize, Serialize};
 
 use crate::model::ResponseError;
 
-use super::{ApiResponse, FeedData, ServiceError};
+use super::{ApiResponse, ChapterAttributes, FeedData, ServiceError};
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ChapterProps {
     pub id: String,
     pub fullname: String,
+    pub manga_id: Option<String>,
+    pub manga_name: Option<String>,
+    pub volume: Option<String>,
+    pub group_id: Option<String>,
+    pub external_url: Option<String>,
+    #[serde(default)]
+    pub availability: ChapterAvailability,
+    /// Language of the translation, tags the EPUB downloads.
+    pub translated_language: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -51,12 +61,14 @@ impl TryFrom<ApiResponse<Vec<FeedData>>> for ChaptersResponse {
 #[serde(rename_all = "camelCase")]
 pub struct Chapter {
     id: String,
-    chapter: String,
+    chapter: Option<String>,
     volume: Option<String>,
     title: Option<String>,
     scan_group: Option<ScanGroup>,
     pages: u32,
     external_url: Option<String>,
+    availability: ChapterAvailability,
+    translated_language: Option<String>,
 }
 
 impl From<&FeedData> for Chapter {
@@ -70,11 +82,36 @@ impl From<&FeedData> for Chapter {
             title: data.attributes.title.to_owned(),
             pages: data.attributes.pages,
             external_url: data.attributes.external_url.to_owned(),
+            availability: ChapterAvailability::of(&data.attributes),
+            translated_language: data.attributes.translated_language.to_owned(),
             scan_group,
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChapterAvailability {
+    #[default]
+    Available,
+    /// Hosted by the publisher, MangaDex only links to it.
+    External,
+    /// Removed or never uploaded, there are no pages to download.
+    Unavailable,
+}
+
+impl ChapterAvailability {
+    pub fn of(attributes: &ChapterAttributes) -> Self {
+        if attributes.external_url.is_some() {
+            ChapterAvailability::External
+        } else if attributes.is_unavailable || attributes.pages == 0 {
+            ChapterAvailability::Unavailable
+        } else {
+            ChapterAvailability::Available
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct ScanGroup {
     name: String,
@@ -155,6 +192,42 @@ mod test {
         assert_eq!(unwrapped.description, Some(description.to_string()));
     }
 
+    #[test]
+    fn detects_chapter_availability() {
+        let mut feed_data = create_scan_group_feed(None, None, None);
+        assert_eq!(
+            ChapterAvailability::of(&feed_data.attributes),
+            ChapterAvailability::Unavailable
+        );
+
+        feed_data.attributes.pages = 20;
+        assert_eq!(
+            ChapterAvailability::of(&feed_data.attributes),
+            ChapterAvailability::Available
+        );
+
+        feed_data.attributes.pages = 0;
+        feed_data.attributes.external_url = Some("https://mangaplus.shueisha.co.jp".to_string());
+        assert_eq!(
+            ChapterAvailability::of(&feed_data.attributes),
+            ChapterAvailability::External
+        );
+    }
+
+    #[test]
+    fn parses_oneshots_without_chapter_number() {
+        let feed_data: FeedData = serde_json::from_value(serde_json::json!({
+            "id": "id",
+            "attributes": { "chapter": null, "volume": null, "title": "Oneshot", "pages": null },
+            "relationships": [],
+        }))
+        .unwrap();
+
+        let chapter = Chapter::from(&feed_data);
+        assert_eq!(chapter.chapter, None);
+        assert_eq!(chapter.availability, ChapterAvailability::Unavailable);
+    }
+
     fn create_scan_group_feed(
         name: Option<String>,
         description: Option<String>,
@@ -163,11 +236,13 @@ mod test {
         FeedData {
             id: "id".to_string(),
             attributes: ChapterAttributes {
-                chapter: "".to_string(),
+                chapter: None,
                 pages: 0,
                 external_url: None,
+                is_unavailable: false,
                 title: None,
                 volume: None,
+                translated_language: None,
             },
             relationships: vec![ChapterRelationship {
                 id: "id".to_string(),
@@ -0,0 +1,175 @@
+use std::io;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use super::{ResponseError, ServiceError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCode {
+    InvalidArguments,
+    Network,
+    /// The proxy or certificate settings are broken, retrying won't help until they change.
+    NetworkSettings,
+    RateLimited,
+    NotFound,
+    Api,
+    Unauthorized,
+    DiskFull,
+    PermissionDenied,
+    Cancelled,
+    Internal,
+}
+
+/// Error sent to the UI, `code` and `retryable` drive what it offers the user.
+#[derive(Debug, Error, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[error("{message}")]
+pub struct CommandError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub retryable: bool,
+    /// Seconds to wait before retrying, when the server said so.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+    /// Details returned by MangaDex for failed api calls.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ResponseError>,
+}
+
+impl CommandError {
+    fn new(code: ErrorCode, message: String) -> Self {
+        // a full disk needs the user to free space first
+        let retryable = matches!(code, ErrorCode::Network | ErrorCode::RateLimited);
+
+        CommandError {
+            code,
+            message,
+            retryable,
+            retry_after: None,
+            errors: Vec::new(),
+        }
+    }
+}
+
+impl From<ServiceError> for CommandError {
+    fn from(e: ServiceError) -> Self {
+        use ServiceError::*;
+
+        let message = e.to_string();
+        let code = match &e {
+            InvalidArguments(_) => ErrorCode::InvalidArguments,
+            ApiError { errors, .. } => match errors.iter().map(ResponseError::status).max() {
+                Some(429) => ErrorCode::RateLimited,
+                Some(404) => ErrorCode::NotFound,
+                Some(401) | Some(403) => ErrorCode::Unauthorized,
+                _ => ErrorCode::Api,
+            },
+            // asking again gets the same response, so it is not retryable
+            MissingData { .. } => ErrorCode::Api,
+            NetworkSettings(_) => ErrorCode::NetworkSettings,
+            HttpError(e) => http_error_code(e),
+            RateLimited { .. } => ErrorCode::RateLimited,
+            Cancelled => ErrorCode::Cancelled,
+            AuthError(_) | Unauthorized | CredentialsError(_) => ErrorCode::Unauthorized,
+            FSError(e) | ZipError(zip::result::ZipError::Io(e)) => io_error_code(e),
+            ParseError(_) | Internal(_) | ZipError(_) | ImageError(_) => ErrorCode::Internal,
+        };
+
+        let mut error = CommandError::new(code, message);
+        match e {
+            ApiError { errors, .. } => {
+                // server side failures are often temporary
+                error.retryable |= errors.iter().any(|e| e.status() >= 500);
+                error.errors = errors;
+            }
+            RateLimited { retry_after } => error.retry_after = retry_after,
+            _ => {}
+        }
+
+        error
+    }
+}
+
+fn http_error_code(e: &reqwest::Error) -> ErrorCode {
+    match e.status().map(|status| status.as_u16()) {
+        Some(429) => ErrorCode::RateLimited,
+        Some(404) => ErrorCode::NotFound,
+        Some(401) | Some(403) => ErrorCode::Unauthorized,
+        Some(status) if status < 500 => ErrorCode::Api,
+        _ if e.is_decode() => ErrorCode::Internal,
+        _ => ErrorCode::Network,
+    }
+}
+
+fn io_error_code(e: &io::Error) -> ErrorCode {
+    match e.kind() {
+        io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+        io::ErrorKind::NotFound => ErrorCode::NotFound,
+        _ if is_disk_full(e) => ErrorCode::DiskFull,
+        _ => ErrorCode::Internal,
+    }
+}
+
+// `ErrorKind::StorageFull` is not stable yet, so the os codes are checked
+fn is_disk_full(e: &io::Error) -> bool {
+    // ERROR_HANDLE_DISK_FULL and ERROR_DISK_FULL
+    #[cfg(windows)]
+    const DISK_FULL: &[i32] = &[39, 112];
+    // ENOSPC
+    #[cfg(not(windows))]
+    const DISK_FULL: &[i32] = &[28];
+
+    e.raw_os_error()
+        .map_or(false, |code| DISK_FULL.contains(&code))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn maps_service_errors_to_codes() {
+        let error = CommandError::from(ServiceError::ApiError {
+            errors: vec![ResponseError::new(429, "too many requests", "slow down")],
+            tag: "feed".to_owned(),
+        });
+        assert_eq!(error.code, ErrorCode::RateLimited);
+        assert!(error.retryable);
+        assert_eq!(error.errors.len(), 1);
+
+        let error = CommandError::from(ServiceError::missing_data("feed", "response has no data"));
+        assert_eq!(error.code, ErrorCode::Api);
+        assert!(!error.retryable);
+
+        let error = CommandError::from(ServiceError::NetworkSettings("bad proxy".to_owned()));
+        assert_eq!(error.code, ErrorCode::NetworkSettings);
+        assert!(!error.retryable);
+
+        let error = CommandError::from(ServiceError::FSError(io::Error::from(
+            io::ErrorKind::PermissionDenied,
+        )));
+        assert_eq!(error.code, ErrorCode::PermissionDenied);
+        assert!(!error.retryable);
+
+        #[cfg(unix)]
+        {
+            let error = CommandError::from(ServiceError::FSError(io::Error::from_raw_os_error(28)));
+            assert_eq!(error.code, ErrorCode::DiskFull);
+            assert!(!error.retryable);
+        }
+    }
+
+    #[test]
+    fn serializes_codes_for_the_ui() {
+        let error = CommandError::from(ServiceError::RateLimited {
+            retry_after: Some(30),
+        });
+        let json = serde_json::to_value(error).unwrap();
+
+        assert_eq!(json["code"], "rateLimited");
+        assert_eq!(json["retryAfter"], 30);
+        assert!(json.get("errors").is_none());
+    }
+}
@@ -0,0 +1,34 @@
+use serde::Deserialize;
+
+use crate::constants::MANGADEX_UPLOADS;
+
+#[derive(Debug, Deserialize)]
+pub struct CoverData {
+    pub id: String,
+    pub attributes: CoverAttributes,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverAttributes {
+    pub volume: Option<String>,
+    pub file_name: String,
+    pub locale: Option<String>,
+}
+
+impl CoverData {
+    pub fn url(&self, manga_id: &str) -> String {
+        format!(
+            "{MANGADEX_UPLOADS}/covers/{manga_id}/{}",
+            self.attributes.file_name
+        )
+    }
+
+    pub fn extension(&self) -> &str {
+        self.attributes
+            .file_name
+            .rsplit_once('.')
+            .map(|(_, ext)| ext)
+            .unwrap_or("jpg")
+    }
+}
@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{CommandError, ServiceError};
+use crate::collision::CollisionPolicy;
+
+/// Per download choices, anything left out comes from the settings.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DownloadOptions {
+    /// Partial output profile, its fields replace the saved or device ones.
+    pub profile: Option<Value>,
+    pub device: Option<String>,
+    pub collision: Option<CollisionPolicy>,
+    /// Stitches long strips into pages as tall as the device screen.
+    pub stitch_strips: Option<bool>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DownloadReport {
+    pub chapters: Vec<ChapterDownload>,
+}
+
+impl DownloadReport {
+    pub fn add(&mut self, chapter_id: &str, status: DownloadStatus) {
+        self.chapters.push(ChapterDownload {
+            chapter_id: chapter_id.to_owned(),
+            status,
+        });
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterDownload {
+    pub chapter_id: String,
+    #[serde(flatten)]
+    pub status: DownloadStatus,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum DownloadStatus {
+    Downloaded {
+        path: PathBuf,
+    },
+    /// Kept as is because of the collision policy or the library.
+    Skipped,
+    /// Only published on another site, e.g. MangaPlus.
+    External {
+        url: String,
+    },
+    /// Listed without any pages on MangaDex.
+    Unavailable,
+    Failed {
+        error: CommandError,
+    },
+}
+
+impl DownloadStatus {
+    pub fn failed(e: ServiceError) -> Self {
+        DownloadStatus::Failed { error: e.into() }
+    }
+}
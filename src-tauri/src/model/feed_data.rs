@@ -1,20 +1,29 @@
 use serde::Deserialize;
 
+use super::lenient::null_as_default;
+
 #[derive(Debug, Deserialize)]
 pub struct FeedData {
     pub id: String,
     pub attributes: ChapterAttributes,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub relationships: Vec<ChapterRelationship>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ChapterAttributes {
     pub volume: Option<String>,
-    pub chapter: String,
+    /// Missing for oneshots.
+    pub chapter: Option<String>,
     pub title: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub pages: u32,
     #[serde(rename = "externalUrl")]
     pub external_url: Option<String>,
+    #[serde(default, rename = "isUnavailable")]
+    pub is_unavailable: bool,
+    #[serde(rename = "translatedLanguage")]
+    pub translated_language: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+/// Treats `null` the same as a missing field.
+pub fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Localized strings, which MangaDex sends as `[]` instead of `{}` when empty.
+pub fn localized<'de, D>(deserializer: D) -> Result<HashMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<Value>::deserialize(deserializer)?;
+
+    Ok(match value {
+        Some(Value::Object(map)) => map
+            .into_iter()
+            .filter_map(|(locale, text)| match text {
+                Value::String(text) => Some((locale, text)),
+                _ => None,
+            })
+            .collect(),
+        _ => HashMap::new(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Attributes {
+        #[serde(default, deserialize_with = "localized")]
+        description: HashMap<String, String>,
+        #[serde(default, deserialize_with = "null_as_default")]
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn accepts_empty_and_null_values() {
+        let attributes: Attributes =
+            serde_json::from_value(json!({ "description": [], "tags": null })).unwrap();
+        assert!(attributes.description.is_empty());
+        assert!(attributes.tags.is_empty());
+
+        let attributes: Attributes =
+            serde_json::from_value(json!({ "description": { "en": "text", "ja": null } })).unwrap();
+        assert_eq!(attributes.description.len(), 1);
+        assert_eq!(attributes.description["en"], "text");
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+const DEFAULT_LOCALE: &str = "en";
+
+/// Preferred locales (e.g. `["ja-ro", "en"]`), falling back to the original
+/// language, its romanized variant and then the alphabetically first locale.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "Vec<String>")]
+pub struct Locales(Vec<String>);
+
+impl Locales {
+    pub fn new(preferred: Vec<String>) -> Self {
+        let mut locales: Vec<String> = Vec::with_capacity(preferred.len());
+
+        for locale in preferred {
+            let locale = locale.trim().to_lowercase();
+            if !locale.is_empty() && !locales.contains(&locale) {
+                locales.push(locale);
+            }
+        }
+
+        if locales.is_empty() {
+            locales.push(DEFAULT_LOCALE.to_owned());
+        }
+
+        Locales(locales)
+    }
+
+    pub fn localize<'a>(
+        &self,
+        values: &'a HashMap<String, String>,
+        original_language: Option<&str>,
+    ) -> Option<&'a String> {
+        values
+            .iter()
+            .filter(|(_, value)| !value.trim().is_empty())
+            .min_by_key(|(locale, _)| (self.rank(locale, original_language), locale.as_str()))
+            .map(|(_, value)| value)
+    }
+
+    pub fn sort_alt_titles(
+        &self,
+        alt_titles: &[HashMap<String, String>],
+        original_language: Option<&str>,
+    ) -> Vec<String> {
+        let mut ranked: Vec<(usize, &String)> = alt_titles
+            .iter()
+            .flat_map(|titles| {
+                let mut entries: Vec<_> = titles.iter().collect();
+                entries.sort();
+                entries
+            })
+            .filter(|(_, title)| !title.trim().is_empty())
+            .map(|(locale, title)| (self.rank(locale, original_language), title))
+            .collect();
+
+        ranked.sort_by_key(|(rank, _)| *rank);
+
+        let mut titles: Vec<String> = Vec::with_capacity(ranked.len());
+        for (_, title) in ranked {
+            if !titles.contains(title) {
+                titles.push(title.to_owned());
+            }
+        }
+
+        titles
+    }
+
+    fn rank(&self, locale: &str, original_language: Option<&str>) -> usize {
+        let locale = locale.to_lowercase();
+        let preferred = self.0.len();
+
+        if let Some(position) = self.0.iter().position(|l| *l == locale) {
+            return position;
+        }
+
+        match original_language.map(str::to_lowercase) {
+            Some(original) if original == locale => preferred,
+            Some(original) if format!("{original}-ro") == locale => preferred + 1,
+            _ => preferred + 2,
+        }
+    }
+}
+
+impl Default for Locales {
+    fn default() -> Self {
+        Locales::new(vec![])
+    }
+}
+
+impl From<Vec<String>> for Locales {
+    fn from(preferred: Vec<String>) -> Self {
+        Locales::new(preferred)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn map(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn follows_preference_chain() {
+        let locales = Locales::new(vec!["ja-ro".to_string(), "en".to_string()]);
+        let titles = map(&[("en", "English"), ("ja-ro", "Romaji"), ("ja", "日本語")]);
+
+        assert_eq!(locales.localize(&titles, Some("ja")).unwrap(), "Romaji");
+    }
+
+    #[test]
+    fn falls_back_to_original_language() {
+        let locales = Locales::new(vec!["de".to_string()]);
+        let titles = map(&[("ko-ro", "Romanized"), ("ko", "한국어"), ("fr", "Français")]);
+
+        assert_eq!(locales.localize(&titles, Some("ko")).unwrap(), "한국어");
+    }
+
+    #[test]
+    fn deterministic_fallback() {
+        let locales = Locales::new(vec!["de".to_string()]);
+        let titles = map(&[("fr", "Français"), ("es", "Español"), ("it", "Italiano")]);
+
+        for _ in 0..10 {
+            assert_eq!(locales.localize(&titles, None).unwrap(), "Español");
+        }
+    }
+
+    #[test]
+    fn skips_empty_values() {
+        let locales = Locales::default();
+        let descriptions = map(&[("en", ""), ("ja", "説明")]);
+
+        assert_eq!(locales.localize(&descriptions, None).unwrap(), "説明");
+    }
+
+    #[test]
+    fn sorts_alt_titles() {
+        let locales = Locales::new(vec!["en".to_string()]);
+        let alt_titles = vec![
+            map(&[("ja", "日本語")]),
+            map(&[("en", "English")]),
+            map(&[("ja-ro", "Romaji")]),
+            map(&[("en", "English")]),
+        ];
+
+        assert_eq!(
+            locales.sort_alt_titles(&alt_titles, Some("ja")),
+            vec!["English", "日本語", "Romaji"]
+        );
+    }
+}
@@ -1,33 +1,94 @@
+use std::collections::HashMap;
+
 use serde::Serialize;
 
-use super::{MangaData, MangaStatistics, MangaView};
+use super::{Locales, MangaData, MangaStatistics, MangaView};
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Manga {
     view: MangaView,
+    alt_titles: Vec<String>,
     year: Option<u32>,
     avg_score: Option<f32>,
+    follows: Option<u32>,
     author: Option<String>,
+    artist: Option<String>,
+    themes: Vec<String>,
+    formats: Vec<String>,
+    long_strip: bool,
+    original_language: Option<String>,
+    demographic: Option<String>,
+    last_volume: Option<String>,
+    last_chapter: Option<String>,
+    links: MangaLinks,
 }
 
 impl Manga {
-    pub fn new(data: &MangaData, stats: &MangaStatistics) -> Self {
-        let view = MangaView::from(data);
+    pub fn new(data: &MangaData, stats: &MangaStatistics, locales: &Locales) -> Self {
+        let view = MangaView::new(data, locales);
+        let attributes = &data.attributes;
+        let original_language = attributes.original_language.as_deref();
+
+        let find_person = |rel_type: &str| {
+            data.relationships
+                .iter()
+                .filter(|rel| rel.rel_type == rel_type)
+                .find_map(|rel| rel.attributes.as_ref()?.name.to_owned())
+        };
+
+        let alt_titles = locales
+            .sort_alt_titles(&attributes.alt_titles, original_language)
+            .into_iter()
+            .filter(|title| title != view.title())
+            .collect();
 
-        let author = data
-            .relationships
-            .iter()
-            .find_map(|rel| match rel.rel_type.as_str() {
-                "author" => rel.attributes.as_ref()?.name.to_owned(),
-                _ => None,
-            });
+        let non_empty = |value: &Option<String>| value.clone().filter(|v| !v.is_empty());
 
         Manga {
             view,
-            year: data.attributes.year,
-            author,
+            alt_titles,
+            year: attributes.year,
             avg_score: stats.rating.average,
+            follows: stats.follows,
+            author: find_person("author"),
+            artist: find_person("artist"),
+            themes: data.tag_names("theme", locales),
+            formats: data.tag_names("format", locales),
+            long_strip: data.is_long_strip(),
+            original_language: attributes.original_language.to_owned(),
+            demographic: attributes.publication_demographic.to_owned(),
+            last_volume: non_empty(&attributes.last_volume),
+            last_chapter: non_empty(&attributes.last_chapter),
+            links: attributes
+                .links
+                .as_ref()
+                .map(MangaLinks::from)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MangaLinks {
+    anilist: Option<String>,
+    my_anime_list: Option<String>,
+    official_raw: Option<String>,
+    official_english: Option<String>,
+}
+
+impl From<&HashMap<String, String>> for MangaLinks {
+    fn from(links: &HashMap<String, String>) -> Self {
+        MangaLinks {
+            anilist: links
+                .get("al")
+                .map(|id| format!("https://anilist.co/manga/{id}")),
+            my_anime_list: links
+                .get("mal")
+                .map(|id| format!("https://myanimelist.net/manga/{id}")),
+            official_raw: links.get("raw").cloned(),
+            official_english: links.get("engtl").cloned(),
         }
     }
 }
@@ -2,21 +2,59 @@ use std::collections::HashMap;
 
 use serde::Deserialize;
 
+use super::lenient::{localized, null_as_default};
+use super::Locales;
+
 #[derive(Debug, Deserialize)]
 pub struct MangaData {
     pub id: String,
     pub attributes: MangaAttributes,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub relationships: Vec<Relationship>,
 }
 
+impl MangaData {
+    pub fn tag_names(&self, group: &str, locales: &Locales) -> Vec<String> {
+        self.attributes
+            .tags
+            .iter()
+            .filter(|t| t.attributes.group == group)
+            .filter_map(|t| locales.localize(&t.attributes.name, None).cloned())
+            .collect()
+    }
+
+    pub fn is_long_strip(&self) -> bool {
+        self.attributes.tags.iter().any(|t| {
+            t.attributes.group == "format"
+                && t.attributes.name.get("en").map(String::as_str) == Some("Long Strip")
+        })
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct MangaAttributes {
+    #[serde(default, deserialize_with = "null_as_default")]
     pub status: String,
+    #[serde(default, deserialize_with = "localized")]
     pub title: HashMap<String, String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub alt_titles: Vec<HashMap<String, String>>,
+    #[serde(default, deserialize_with = "localized")]
     pub description: HashMap<String, String>,
-    #[serde(rename = "availableTranslatedLanguages")]
+    #[serde(
+        rename = "availableTranslatedLanguages",
+        default,
+        deserialize_with = "null_as_default"
+    )]
     pub translations: Vec<String>,
+    pub original_language: Option<String>,
+    pub publication_demographic: Option<String>,
+    pub last_volume: Option<String>,
+    pub last_chapter: Option<String>,
+    pub links: Option<HashMap<String, String>>,
     pub year: Option<u32>,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub tags: Vec<Tag>,
 }
 
@@ -28,6 +66,7 @@ pub struct Tag {
 #[derive(Debug, Deserialize, Clone)]
 pub struct TagAttributes {
     pub group: String,
+    #[serde(default, deserialize_with = "localized")]
     pub name: HashMap<String, String>,
 }
 
@@ -1,9 +1,9 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use super::MangaData;
+use super::{Locales, MangaData};
 use crate::constants::MANGADEX_UPLOADS;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MangaView {
     id: String,
@@ -14,18 +14,14 @@ pub struct MangaView {
     genres: Vec<String>,
 }
 
-impl From<&MangaData> for MangaView {
-    fn from(manga: &MangaData) -> Self {
-        let mut title_map = manga.attributes.title.clone();
+impl MangaView {
+    pub fn new(manga: &MangaData, locales: &Locales) -> Self {
+        let original_language = manga.attributes.original_language.as_deref();
 
-        let title = title_map.remove("en").unwrap_or_else(|| {
-            let mut title_iter = title_map.into_iter();
-
-            match title_iter.next() {
-                Some((_, value)) => value,
-                None => "".to_string(),
-            }
-        });
+        let title = locales
+            .localize(&manga.attributes.title, original_language)
+            .cloned()
+            .unwrap_or_default();
 
         let cover_url: Option<String> = manga
             .relationships
@@ -41,32 +37,25 @@ impl From<&MangaData> for MangaView {
                 Some(url)
             });
 
-        let description_map = &manga.attributes.description;
-        let description = description_map
-            .get("en")
-            .or_else(|| {
-                let mut description_iter = description_map.iter();
-                description_iter.next().map(|(_, v)| v)
-            })
+        let description = locales
+            .localize(&manga.attributes.description, original_language)
             .cloned();
 
-        let genres: Vec<String> = manga
-            .attributes
-            .tags
-            .iter()
-            .filter_map(|t| match t.attributes.group.as_str() {
-                "genre" => Some(t.attributes.name.get("en")?.to_owned()),
-                _ => None,
-            })
-            .collect();
-
         MangaView {
             id: manga.id.to_owned(),
             title,
             status: manga.attributes.status.to_owned(),
             cover_url,
             description,
-            genres,
+            genres: manga.tag_names("genre", locales),
         }
     }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
 }
@@ -1,7 +1,13 @@
 pub mod aggregate;
 pub mod at_home;
+pub mod auth;
 pub mod chapter;
+pub mod command_error;
+pub mod cover;
+pub mod download;
 pub mod feed_data;
+pub mod lenient;
+pub mod locale;
 pub mod manga;
 pub mod manga_data;
 pub mod manga_view;
@@ -10,8 +16,13 @@ pub mod statistics;
 
 pub use aggregate::*;
 pub use at_home::*;
+pub use auth::*;
 pub use chapter::*;
+pub use command_error::*;
+pub use cover::*;
+pub use download::*;
 pub use feed_data::*;
+pub use locale::*;
 pub use manga::*;
 pub use manga_data::*;
 pub use manga_view::*;
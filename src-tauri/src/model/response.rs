@@ -16,7 +16,9 @@ pub struct ApiResponse<T> {
 impl<T> ApiResponse<T> {
     pub fn result(self, tag: &str) -> Result<T> {
         match self.result.as_str() {
-            "ok" => Ok(self.data.unwrap()),
+            "ok" => self
+                .data
+                .ok_or_else(|| ServiceError::missing_data(tag, "response has no data")),
             _ => Err(ServiceError::ApiError {
                 errors: self
                     .errors
@@ -27,7 +29,7 @@ impl<T> ApiResponse<T> {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ResponseError {
     id: String,
     status: u32,
@@ -36,6 +38,10 @@ pub struct ResponseError {
 }
 
 impl ResponseError {
+    pub fn status(&self) -> u32 {
+        self.status
+    }
+
     pub fn new(status: u32, title: &str, detail: &str) -> Self {
         Self {
             id: Default::default(),
@@ -68,17 +74,53 @@ pub enum ServiceError {
         tag: String,
     },
 
-    #[error("failed http requests")]
+    #[error("incomplete response from api \"{}\": {}", .tag, .detail)]
+    MissingData { tag: String, detail: String },
+
+    #[error("network settings could not be applied: {}", .0)]
+    NetworkSettings(String),
+
+    #[error("failed http request: {}", .0)]
     HttpError(#[from] reqwest::Error),
 
+    #[error("failed to parse response: {}", .0)]
+    ParseError(#[from] serde_json::Error),
+
+    #[error("rate limited by api")]
+    RateLimited { retry_after: Option<u64> },
+
+    #[error("operation was cancelled")]
+    Cancelled,
+
     #[error("something went wrong: {}", .0)]
     Internal(String),
 
-    #[error("failed file operation")]
+    #[error("authentication failed: {}", .0)]
+    AuthError(String),
+
+    #[error("not logged in")]
+    Unauthorized,
+
+    #[error("failed to access credentials storage: {}", .0)]
+    CredentialsError(#[from] keyring::Error),
+
+    #[error("failed file operation: {}", .0)]
     FSError(#[from] io::Error),
 
-    #[error("failed to create zip archive")]
+    #[error("failed to create zip archive: {}", .0)]
     ZipError(#[from] zip::result::ZipError),
+
+    #[error("failed to process image: {}", .0)]
+    ImageError(#[from] image::ImageError),
+}
+
+impl ServiceError {
+    pub fn missing_data(tag: &str, detail: &str) -> Self {
+        ServiceError::MissingData {
+            tag: tag.to_owned(),
+            detail: detail.to_owned(),
+        }
+    }
 }
 
 pub type Result<T> = result::Result<T, ServiceError>;
@@ -9,10 +9,12 @@ pub struct StatisticsResponse {
 
 #[derive(Debug, Deserialize)]
 pub struct MangaStatistics {
+    #[serde(default)]
     pub rating: Rating,
+    pub follows: Option<u32>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct Rating {
     pub average: Option<f32>,
 }
@@ -0,0 +1,333 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fs;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use bytes::Bytes;
+use futures::stream::{self, StreamExt};
+use log::{debug, warn};
+use tauri::http::{Request, Response, ResponseBuilder};
+use tauri::{AppHandle, Manager};
+use tokio::sync::Notify;
+
+use crate::cache;
+use crate::constants::{PAGES_CACHE_MAX_BYTES, PAGES_CACHE_TTL};
+use crate::http;
+use crate::limits::RequestLimiter;
+use crate::model::{AtHomeResponse, Result, ServiceError};
+use crate::reader::{mime_type, parse_page_uri, protocol_url, PageView};
+use crate::service;
+
+pub const CHAPTER_PROTOCOL: &str = "chapter";
+
+const PREFETCH_PAGES: usize = 4;
+const CACHED_PAGES: usize = 32;
+
+#[derive(Default)]
+pub struct OnlineReader {
+    chapters: Mutex<HashMap<String, Arc<ChapterPages>>>,
+    pages: Mutex<VecDeque<(String, Bytes)>>,
+    /// Pages being downloaded, by chapter id and index.
+    in_flight: Mutex<HashSet<(String, usize)>>,
+    /// Woken whenever an in-flight download ends, successful or not.
+    fetched: Notify,
+}
+
+// removes the page from the in-flight set when the download ends or is dropped
+struct InFlight<'a> {
+    reader: &'a OnlineReader,
+    page: (String, usize),
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.reader.lock_in_flight().remove(&self.page);
+        self.reader.fetched.notify_waiters();
+    }
+}
+
+struct ChapterPages {
+    base_url: String,
+    hash: String,
+    files: Vec<String>,
+}
+
+impl From<AtHomeResponse> for ChapterPages {
+    fn from(at_home: AtHomeResponse) -> Self {
+        ChapterPages {
+            base_url: at_home.base_url,
+            hash: at_home.chapter.hash,
+            files: at_home.chapter.data_saver,
+        }
+    }
+}
+
+impl OnlineReader {
+    pub async fn open(&self, chapter_id: &str) -> Result<Vec<PageView>> {
+        let pages = self.resolve(chapter_id).await?;
+
+        tauri::async_runtime::spawn_blocking(|| {
+            if let Err(e) = cache::prune_pages(PAGES_CACHE_MAX_BYTES, PAGES_CACHE_TTL) {
+                warn!("failed to prune cached pages: {e}");
+            }
+        });
+
+        Ok(pages
+            .files
+            .iter()
+            .enumerate()
+            .map(|(index, file_name)| {
+                PageView::new(index, file_name, chapter_page_url(chapter_id, index))
+            })
+            .collect())
+    }
+
+    pub fn close(&self, chapter_id: &str) {
+        self.lock_chapters().remove(chapter_id);
+
+        let prefix = page_key(chapter_id, "");
+        self.lock_pages()
+            .retain(|(key, _)| !key.starts_with(&prefix));
+    }
+
+    pub async fn page(
+        &self,
+        chapter_id: &str,
+        index: usize,
+        limiter: &RequestLimiter,
+    ) -> Result<(String, Bytes)> {
+        // a page that is already downloading is waited for instead of being
+        // requested again, the next round finds it in the cache
+        let (_in_flight, pages, mut file_name) = loop {
+            let fetched = self.fetched.notified();
+            let pages = self.get_or_resolve(chapter_id).await?;
+            let file_name = page_file(&pages, index)?;
+
+            if let Some(data) = self.cached(chapter_id, &file_name) {
+                return Ok((file_name, data));
+            }
+
+            match self.start_fetch(chapter_id, index) {
+                Some(in_flight) => break (in_flight, pages, file_name),
+                None => fetched.await,
+            }
+        };
+
+        let frame_url = service::get_frame_url(&pages.base_url, &pages.hash, &file_name);
+        let data = match service::fetch_frame(&http::client()?, &frame_url, limiter).await {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("failed to fetch page {index} of {chapter_id}, refreshing server: {e}");
+
+                // the refreshed list may name or number the pages differently
+                let pages = self.resolve(chapter_id).await?;
+                file_name = page_file(&pages, index)?;
+                let frame_url = service::get_frame_url(&pages.base_url, &pages.hash, &file_name);
+
+                service::fetch_frame(&http::client()?, &frame_url, limiter).await?
+            }
+        };
+
+        self.store(chapter_id, &file_name, &data);
+
+        Ok((file_name, data))
+    }
+
+    /// Page from memory or disk without touching the network, for the
+    /// synchronous protocol handler.
+    pub fn cached_page(&self, chapter_id: &str, index: usize) -> Option<(String, Bytes)> {
+        let pages = self.lock_chapters().get(chapter_id).cloned()?;
+        let file_name = pages.files.get(index)?;
+        let data = self.cached(chapter_id, file_name)?;
+
+        Some((file_name.to_owned(), data))
+    }
+
+    pub async fn prefetch(
+        &self,
+        chapter_id: &str,
+        from: usize,
+        count: usize,
+        limiter: &RequestLimiter,
+    ) -> Result<()> {
+        let pages = self.get_or_resolve(chapter_id).await?;
+        let to = pages.files.len().min(from.saturating_add(count));
+
+        // pages already downloading are left to the request that started them
+        let missing: Vec<usize> = (from..to)
+            .filter(|&index| !self.is_in_flight(chapter_id, index))
+            .collect();
+
+        stream::iter(missing)
+            .map(|index| self.page(chapter_id, index, limiter))
+            .buffer_unordered(count.max(1))
+            .for_each(|result| async {
+                if let Err(e) = result {
+                    debug!("failed to prefetch page of {chapter_id}: {e}");
+                }
+            })
+            .await;
+
+        Ok(())
+    }
+
+    async fn get_or_resolve(&self, chapter_id: &str) -> Result<Arc<ChapterPages>> {
+        let existing = self.lock_chapters().get(chapter_id).cloned();
+
+        match existing {
+            Some(pages) => Ok(pages),
+            None => self.resolve(chapter_id).await,
+        }
+    }
+
+    async fn resolve(&self, chapter_id: &str) -> Result<Arc<ChapterPages>> {
+        let at_home = service::get_at_home(chapter_id).await?;
+        let pages = Arc::new(ChapterPages::from(at_home));
+
+        self.lock_chapters()
+            .insert(chapter_id.to_owned(), pages.clone());
+
+        Ok(pages)
+    }
+
+    fn start_fetch(&self, chapter_id: &str, index: usize) -> Option<InFlight<'_>> {
+        let page = (chapter_id.to_owned(), index);
+
+        if self.lock_in_flight().insert(page.clone()) {
+            Some(InFlight { reader: self, page })
+        } else {
+            None
+        }
+    }
+
+    fn is_in_flight(&self, chapter_id: &str, index: usize) -> bool {
+        self.lock_in_flight()
+            .contains(&(chapter_id.to_owned(), index))
+    }
+
+    fn cached(&self, chapter_id: &str, file_name: &str) -> Option<Bytes> {
+        let key = page_key(chapter_id, file_name);
+        let in_memory = self
+            .lock_pages()
+            .iter()
+            .find(|(cached, _)| *cached == key)
+            .map(|(_, data)| data.clone());
+
+        in_memory.or_else(|| {
+            let path = cache::get_pages_dir(chapter_id)?.join(file_name);
+            let data = Bytes::from(fs::read(path).ok()?);
+            self.remember(key, &data);
+
+            Some(data)
+        })
+    }
+
+    fn store(&self, chapter_id: &str, file_name: &str, data: &Bytes) {
+        self.remember(page_key(chapter_id, file_name), data);
+
+        if let Some(dir) = cache::get_pages_dir(chapter_id) {
+            let result =
+                fs::create_dir_all(&dir).and_then(|_| fs::write(dir.join(file_name), data));
+
+            if let Err(e) = result {
+                warn!("failed to cache page {file_name} of {chapter_id}: {e}");
+            }
+        }
+    }
+
+    fn remember(&self, key: String, data: &Bytes) {
+        let mut pages = self.lock_pages();
+
+        if pages.iter().any(|(cached, _)| *cached == key) {
+            return;
+        }
+        if pages.len() == CACHED_PAGES {
+            pages.pop_front();
+        }
+        pages.push_back((key, data.clone()));
+    }
+
+    fn lock_chapters(&self) -> MutexGuard<'_, HashMap<String, Arc<ChapterPages>>> {
+        self.chapters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_pages(&self) -> MutexGuard<'_, VecDeque<(String, Bytes)>> {
+        self.pages.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_in_flight(&self) -> MutexGuard<'_, HashSet<(String, usize)>> {
+        self.in_flight.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub fn handle_protocol(
+    app: &AppHandle,
+    request: &Request,
+) -> std::result::Result<Response, Box<dyn Error>> {
+    let (chapter_id, index) = match parse_page_uri(request.uri()) {
+        Some(page) => page,
+        None => return ResponseBuilder::new().status(400).body(Vec::new()),
+    };
+
+    // the handler runs on the webview thread, so it never waits for the
+    // network, a missing page is fetched in the background for a retry
+    let page = app.state::<OnlineReader>().cached_page(&chapter_id, index);
+    let from = if page.is_some() { index + 1 } else { index };
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let online = app.state::<OnlineReader>();
+        let limiter = app.state::<RequestLimiter>();
+        if let Err(e) = online
+            .prefetch(&chapter_id, from, PREFETCH_PAGES, &limiter)
+            .await
+        {
+            debug!("failed to prefetch pages of chapter {chapter_id}: {e}");
+        }
+    });
+
+    match page {
+        Some((file_name, data)) => ResponseBuilder::new()
+            .status(200)
+            .mimetype(mime_type(&file_name))
+            .body(data.to_vec()),
+        None => ResponseBuilder::new()
+            .status(503)
+            .header("Retry-After", "1")
+            .body(Vec::new()),
+    }
+}
+
+fn page_file(pages: &ChapterPages, index: usize) -> Result<String> {
+    pages
+        .files
+        .get(index)
+        .cloned()
+        .ok_or_else(|| ServiceError::InvalidArguments(format!("page {index} not found")))
+}
+
+fn page_key(chapter_id: &str, file_name: &str) -> String {
+    format!("{chapter_id}/{file_name}")
+}
+
+pub fn chapter_page_url(chapter_id: &str, index: usize) -> String {
+    protocol_url(CHAPTER_PROTOCOL, chapter_id, index)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fetches_each_page_once() {
+        let reader = OnlineReader::default();
+
+        let in_flight = reader.start_fetch("chapter", 1);
+        assert!(in_flight.is_some());
+        assert!(reader.start_fetch("chapter", 1).is_none());
+        assert!(reader.start_fetch("chapter", 2).is_some());
+
+        drop(in_flight);
+        assert!(!reader.is_in_flight("chapter", 1));
+    }
+}
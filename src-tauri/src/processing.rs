@@ -0,0 +1,585 @@
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{imageops, DynamicImage, GenericImageView, RgbImage};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::model::{Result, ServiceError};
+
+const WHITE_THRESHOLD: u8 = 240;
+// max luma spread for a row to count as a gutter between long strip panels
+const GUTTER_TOLERANCE: u8 = 16;
+// page height of stitched strips for screens without a known height
+const DEFAULT_STRIP_HEIGHT: u32 = 2000;
+// stitched pages are only counted at the end, four digits fit any chapter
+const STRIP_PAD_WIDTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Original,
+    Jpeg,
+    Png,
+    Webp,
+    Avif,
+}
+
+impl OutputFormat {
+    fn extension(&self) -> Option<&'static str> {
+        match self {
+            OutputFormat::Original => None,
+            OutputFormat::Jpeg => Some("jpg"),
+            OutputFormat::Png => Some("png"),
+            OutputFormat::Webp => Some("webp"),
+            OutputFormat::Avif => Some("avif"),
+        }
+    }
+
+    fn from_extension(ext: &str) -> OutputFormat {
+        match ext.to_lowercase().as_str() {
+            "jpg" | "jpeg" => OutputFormat::Jpeg,
+            "webp" => OutputFormat::Webp,
+            "avif" => OutputFormat::Avif,
+            _ => OutputFormat::Png,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OutputProfile {
+    pub format: OutputFormat,
+    /// Encoder quality (1-100) for JPEG and AVIF, PNG and WebP are always
+    /// written lossless.
+    pub quality: u8,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    pub grayscale: bool,
+    /// Contrast change in percent, negative values decrease contrast.
+    pub contrast: f32,
+    pub trim_borders: bool,
+    pub split_spreads: bool,
+    pub right_to_left: bool,
+    /// Stitches long strip frames and re-slices them at this page height.
+    pub strip_height: Option<u32>,
+}
+
+impl Default for OutputProfile {
+    fn default() -> Self {
+        OutputProfile {
+            format: OutputFormat::Original,
+            quality: 85,
+            max_width: None,
+            max_height: None,
+            grayscale: false,
+            contrast: 0.0,
+            trim_borders: false,
+            split_spreads: false,
+            right_to_left: true,
+            strip_height: None,
+        }
+    }
+}
+
+impl OutputProfile {
+    pub fn is_passthrough(&self) -> bool {
+        self.format == OutputFormat::Original
+            && self.max_width.is_none()
+            && self.max_height.is_none()
+            && !self.grayscale
+            && self.contrast == 0.0
+            && !self.trim_borders
+            && !self.split_spreads
+            && self.strip_height.is_none()
+    }
+
+    /// Replaces the fields named in `overrides`, a partial profile in the
+    /// same form the settings are saved in.
+    pub fn with_overrides(&self, overrides: Value) -> Result<OutputProfile> {
+        let invalid = |e: serde_json::Error| {
+            ServiceError::InvalidArguments(format!("invalid output profile: {e}"))
+        };
+
+        let mut profile = serde_json::to_value(self).map_err(invalid)?;
+        match (profile.as_object_mut(), overrides) {
+            (Some(fields), Value::Object(overrides)) => fields.extend(overrides),
+            (_, Value::Null) => {}
+            _ => {
+                return Err(ServiceError::InvalidArguments(
+                    "output profile must be an object".to_owned(),
+                ))
+            }
+        }
+
+        serde_json::from_value(profile).map_err(invalid)
+    }
+
+    /// Turns long strip stitching on or off, stitched pages are as tall as
+    /// the device screen unless the profile sets its own height.
+    pub fn with_stitching(mut self, stitch: bool) -> OutputProfile {
+        self.strip_height = if stitch {
+            self.strip_height
+                .or(self.max_height)
+                .or(Some(DEFAULT_STRIP_HEIGHT))
+        } else {
+            None
+        };
+
+        self
+    }
+
+    /// Encoder quality of the written pages, lossless output counts as 100.
+    pub fn effective_quality(&self) -> u8 {
+        match self.format {
+            OutputFormat::Original if self.is_passthrough() => 100,
+            OutputFormat::Png | OutputFormat::Webp => 100,
+            _ => self.quality.clamp(1, 100),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ProcessedPage {
+    pub data: Vec<u8>,
+    pub extension: String,
+}
+
+/// A page ready to be written into an archive.
+#[derive(Debug)]
+pub struct NamedPage {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Processes the frames of a chapter one by one, in page order.
+pub struct ChapterProcessor {
+    profile: OutputProfile,
+    pad_width: usize,
+    strip: Option<Strip>,
+}
+
+struct Strip {
+    image: RgbImage,
+    extension: String,
+    pages: usize,
+}
+
+impl ChapterProcessor {
+    pub fn new(profile: OutputProfile, pad_width: usize) -> Self {
+        ChapterProcessor {
+            profile,
+            pad_width,
+            strip: None,
+        }
+    }
+
+    pub fn push(&mut self, frame_name: &str, data: &[u8]) -> Result<Vec<NamedPage>> {
+        let (stem, extension) = frame_name.rsplit_once('.').unwrap_or((frame_name, "jpg"));
+
+        if let Some(strip_height) = self.profile.strip_height {
+            return self.stitch(data, extension, strip_height);
+        }
+
+        let pages = process_image(data, extension, &self.profile)?;
+        let is_split = pages.len() > 1;
+
+        Ok(pages
+            .into_iter()
+            .enumerate()
+            .map(|(index, page)| {
+                let suffix = if is_split {
+                    char::from(b'a' + index as u8).to_string()
+                } else {
+                    String::new()
+                };
+
+                NamedPage {
+                    name: format!("{stem}{suffix}.{}", page.extension),
+                    data: page.data,
+                }
+            })
+            .collect())
+    }
+
+    /// Flushes whatever is left of a stitched strip.
+    pub fn finish(mut self) -> Result<Vec<NamedPage>> {
+        match self.strip.take() {
+            Some(strip) if strip.image.height() > 0 => {
+                let Strip {
+                    image,
+                    extension,
+                    pages,
+                } = strip;
+
+                Ok(vec![self.strip_page(image, &extension, pages + 1)?])
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    // frames are appended to a strip which is cut into pages as soon as it is
+    // tall enough, so only about one page worth of pixels is kept in memory
+    fn stitch(
+        &mut self,
+        data: &[u8],
+        extension: &str,
+        strip_height: u32,
+    ) -> Result<Vec<NamedPage>> {
+        let strip_height = strip_height.max(1);
+        let mut frame = image::load_from_memory(data)?.to_rgb8();
+
+        let mut strip = match self.strip.take() {
+            Some(mut strip) => {
+                let width = strip.image.width();
+                if frame.width() != width {
+                    let height = frame.height() * width / frame.width().max(1);
+                    frame = imageops::resize(&frame, width, height, FilterType::Lanczos3);
+                }
+                strip.image = append_strip(&strip.image, &frame);
+                strip
+            }
+            None => Strip {
+                image: frame,
+                extension: extension.to_owned(),
+                pages: 0,
+            },
+        };
+
+        let mut pages = Vec::new();
+
+        while strip.image.height() > strip_height {
+            let cut = find_gutter(&strip.image, strip_height);
+            let (width, height) = strip.image.dimensions();
+            let page = imageops::crop_imm(&strip.image, 0, 0, width, cut).to_image();
+
+            strip.pages += 1;
+            pages.push(self.strip_page(page, &strip.extension, strip.pages)?);
+            strip.image = imageops::crop_imm(&strip.image, 0, cut, width, height - cut).to_image();
+        }
+
+        self.strip = Some(strip);
+
+        Ok(pages)
+    }
+
+    fn strip_page(&self, image: RgbImage, extension: &str, number: usize) -> Result<NamedPage> {
+        let page = render_page(DynamicImage::ImageRgb8(image), extension, &self.profile)?;
+
+        Ok(NamedPage {
+            name: page_name(number, self.pad_width, &page.extension),
+            data: page.data,
+        })
+    }
+}
+
+// pages are one-based with page 0 reserved for the cover and padded to the
+// widest page number, so names sort the same lexicographically and naturally
+pub fn page_name(number: usize, pad_width: usize, extension: &str) -> String {
+    format!("{number:0pad_width$}.{extension}")
+}
+
+/// Digits of the page numbers of a chapter with `total_frames` frames.
+pub fn page_pad_width(total_frames: usize, profile: &OutputProfile) -> usize {
+    let pad_width = total_frames.to_string().len();
+
+    match profile.strip_height {
+        Some(_) => pad_width.max(STRIP_PAD_WIDTH),
+        None => pad_width,
+    }
+}
+
+pub fn process_image(
+    data: &[u8],
+    extension: &str,
+    profile: &OutputProfile,
+) -> Result<Vec<ProcessedPage>> {
+    if profile.is_passthrough() {
+        return Ok(vec![ProcessedPage {
+            data: data.to_vec(),
+            extension: extension.to_owned(),
+        }]);
+    }
+
+    let mut image = image::load_from_memory(data)?;
+
+    if profile.trim_borders {
+        image = trim_borders(image);
+    }
+
+    let halves = if profile.split_spreads && image.width() > image.height() {
+        split_spread(&image, profile.right_to_left)
+    } else {
+        vec![image]
+    };
+
+    halves
+        .into_iter()
+        .map(|page| render_page(page, extension, profile))
+        .collect()
+}
+
+fn render_page(
+    mut page: DynamicImage,
+    extension: &str,
+    profile: &OutputProfile,
+) -> Result<ProcessedPage> {
+    let format = match profile.format {
+        OutputFormat::Original => OutputFormat::from_extension(extension),
+        format => format,
+    };
+
+    let max_width = profile.max_width.unwrap_or(u32::MAX);
+    let max_height = profile.max_height.unwrap_or(u32::MAX);
+    if page.width() > max_width || page.height() > max_height {
+        page = page.resize(max_width, max_height, FilterType::Lanczos3);
+    }
+
+    if profile.grayscale {
+        page = page.grayscale();
+    }
+
+    if profile.contrast != 0.0 {
+        page = page.adjust_contrast(profile.contrast);
+    }
+
+    Ok(ProcessedPage {
+        data: encode(&page, format, profile.quality)?,
+        extension: format.extension().unwrap_or(extension).to_owned(),
+    })
+}
+
+fn append_strip(top: &RgbImage, bottom: &RgbImage) -> RgbImage {
+    let mut strip = RgbImage::new(top.width(), top.height() + bottom.height());
+    imageops::replace(&mut strip, top, 0, 0);
+    imageops::replace(&mut strip, bottom, 0, i64::from(top.height()));
+
+    strip
+}
+
+// closest uniformly colored row above the target height, looking up to a third
+// of a page back, or the target height when the panels never break
+fn find_gutter(strip: &RgbImage, target: u32) -> u32 {
+    let min_cut = (target - target / 3).max(1);
+
+    (min_cut..=target)
+        .rev()
+        .find(|&y| is_uniform_row(strip, y))
+        .unwrap_or(target)
+}
+
+fn is_uniform_row(strip: &RgbImage, y: u32) -> bool {
+    let (mut min, mut max) = (u8::MAX, u8::MIN);
+
+    for x in 0..strip.width() {
+        let [r, g, b] = strip.get_pixel(x, y).0;
+        let luma = ((u16::from(r) + u16::from(g) + u16::from(b)) / 3) as u8;
+        min = min.min(luma);
+        max = max.max(luma);
+    }
+
+    max - min <= GUTTER_TOLERANCE
+}
+
+fn trim_borders(image: DynamicImage) -> DynamicImage {
+    let luma = image.to_luma8();
+    let (width, height) = luma.dimensions();
+
+    let mut left = width;
+    let mut right = 0;
+    let mut top = height;
+    let mut bottom = 0;
+
+    for (x, y, pixel) in luma.enumerate_pixels() {
+        if pixel.0[0] < WHITE_THRESHOLD {
+            left = left.min(x);
+            right = right.max(x);
+            top = top.min(y);
+            bottom = bottom.max(y);
+        }
+    }
+
+    if left > right || top > bottom {
+        return image;
+    }
+
+    image.crop_imm(left, top, right - left + 1, bottom - top + 1)
+}
+
+fn split_spread(image: &DynamicImage, right_to_left: bool) -> Vec<DynamicImage> {
+    let (width, height) = image.dimensions();
+    let half = width / 2;
+    let left = image.crop_imm(0, 0, half, height);
+    let right = image.crop_imm(half, 0, width - half, height);
+
+    if right_to_left {
+        vec![right, left]
+    } else {
+        vec![left, right]
+    }
+}
+
+/// Re-encodes any supported image as JPEG, e.g. for series covers.
+pub fn to_jpeg(data: &[u8], quality: u8) -> Result<Vec<u8>> {
+    encode(&image::load_from_memory(data)?, OutputFormat::Jpeg, quality)
+}
+
+fn encode(image: &DynamicImage, format: OutputFormat, quality: u8) -> Result<Vec<u8>> {
+    let quality = quality.clamp(1, 100);
+    let mut buffer = Cursor::new(Vec::new());
+
+    match format {
+        OutputFormat::Jpeg => {
+            let image = match image {
+                DynamicImage::ImageLuma8(_) => image.clone(),
+                _ => DynamicImage::ImageRgb8(image.to_rgb8()),
+            };
+            image.write_with_encoder(JpegEncoder::new_with_quality(&mut buffer, quality))?;
+        }
+        // the bundled webp codec only encodes lossless, quality does not apply
+        OutputFormat::Webp => {
+            DynamicImage::ImageRgb8(image.to_rgb8())
+                .write_with_encoder(WebPEncoder::new_lossless(&mut buffer))?;
+        }
+        OutputFormat::Avif => encode_avif(image, &mut buffer, quality)?,
+        OutputFormat::Png | OutputFormat::Original => {
+            image.write_with_encoder(PngEncoder::new(&mut buffer))?;
+        }
+    }
+
+    Ok(buffer.into_inner())
+}
+
+#[cfg(feature = "avif")]
+fn encode_avif(image: &DynamicImage, buffer: &mut Cursor<Vec<u8>>, quality: u8) -> Result<()> {
+    use image::codecs::avif::AvifEncoder;
+
+    DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_with_encoder(AvifEncoder::new_with_speed_quality(buffer, 8, quality))?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "avif"))]
+fn encode_avif(_image: &DynamicImage, _buffer: &mut Cursor<Vec<u8>>, _quality: u8) -> Result<()> {
+    Err(ServiceError::InvalidArguments(
+        "app was built without avif support".to_owned(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use image::{GrayImage, Luma};
+
+    use super::*;
+
+    fn encoded_page(width: u32, height: u32, draw: impl Fn(u32, u32) -> u8) -> Vec<u8> {
+        let page = GrayImage::from_fn(width, height, |x, y| Luma([draw(x, y)]));
+        encode(&DynamicImage::ImageLuma8(page), OutputFormat::Png, 100).unwrap()
+    }
+
+    fn decode(page: &ProcessedPage) -> DynamicImage {
+        image::load_from_memory(&page.data).unwrap()
+    }
+
+    #[test]
+    fn trims_white_borders() {
+        let data = encoded_page(20, 30, |x, y| {
+            if (5..15).contains(&x) && (10..20).contains(&y) {
+                0
+            } else {
+                255
+            }
+        });
+        let profile = OutputProfile {
+            trim_borders: true,
+            ..Default::default()
+        };
+
+        let pages = process_image(&data, "png", &profile).unwrap();
+
+        assert_eq!(decode(&pages[0]).dimensions(), (10, 10));
+    }
+
+    #[test]
+    fn splits_spreads_right_to_left() {
+        let data = encoded_page(40, 20, |x, _| if x < 20 { 0 } else { 255 });
+        let profile = OutputProfile {
+            split_spreads: true,
+            ..Default::default()
+        };
+
+        let pages = process_image(&data, "png", &profile).unwrap();
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(decode(&pages[0]).to_luma8().get_pixel(0, 0).0[0], 255);
+        assert_eq!(decode(&pages[1]).to_luma8().get_pixel(0, 0).0[0], 0);
+    }
+
+    #[test]
+    fn downscales_and_converts() {
+        let data = encoded_page(100, 200, |_, _| 128);
+        let profile = OutputProfile {
+            format: OutputFormat::Jpeg,
+            max_height: Some(50),
+            ..Default::default()
+        };
+
+        let pages = process_image(&data, "png", &profile).unwrap();
+
+        assert_eq!(pages[0].extension, "jpg");
+        assert_eq!(decode(&pages[0]).dimensions(), (25, 50));
+    }
+
+    #[test]
+    fn cuts_strips_at_gutters() {
+        let strip = RgbImage::from_fn(10, 100, |x, y| {
+            if (60..62).contains(&y) || x % 2 == 0 {
+                image::Rgb([255, 255, 255])
+            } else {
+                image::Rgb([0, 0, 0])
+            }
+        });
+
+        assert_eq!(find_gutter(&strip, 70), 61);
+        assert_eq!(find_gutter(&strip, 40), 40);
+    }
+
+    #[test]
+    fn stitches_frames_into_pages() {
+        let profile = OutputProfile {
+            format: OutputFormat::Png,
+            strip_height: Some(60),
+            ..Default::default()
+        };
+        let mut processor = ChapterProcessor::new(profile.clone(), page_pad_width(3, &profile));
+
+        let mut pages = Vec::new();
+        for name in ["1.png", "2.png", "3.png"] {
+            pages.extend(
+                processor
+                    .push(name, &encoded_page(10, 50, |_, _| 255))
+                    .unwrap(),
+            );
+        }
+        pages.extend(processor.finish().unwrap());
+
+        let names: Vec<&str> = pages.iter().map(|page| page.name.as_str()).collect();
+        assert_eq!(names, ["0001.png", "0002.png", "0003.png"]);
+        assert_eq!(
+            image::load_from_memory(&pages[2].data).unwrap().height(),
+            30
+        );
+    }
+
+    #[test]
+    fn keeps_original_data_without_changes() {
+        let data = vec![1, 2, 3];
+        let pages = process_image(&data, "jpg", &OutputProfile::default()).unwrap();
+
+        assert_eq!(pages[0].data, data);
+    }
+}
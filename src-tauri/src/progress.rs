@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::constants::APP_DIR_NAME;
+use crate::model::{ChapterAggregate, Result};
+use crate::store::JsonStore;
+use crate::time::unix_now;
+
+const PROGRESS_FILE: &str = "progress.json";
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterProgress {
+    pub read: bool,
+    pub last_page: Option<u32>,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ProgressData {
+    manga: HashMap<String, HashMap<String, ChapterProgress>>,
+    /// when each manga's read markers were last synced with MangaDex
+    #[serde(default)]
+    synced_at: HashMap<String, u64>,
+}
+
+/// Read marker changes needed to bring local progress and MangaDex in line.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MarkersSyncPlan {
+    pub pull_read: Vec<String>,
+    pub pull_unread: Vec<String>,
+    pub push_read: Vec<String>,
+    pub push_unread: Vec<String>,
+}
+
+pub struct ReadProgress {
+    store: JsonStore<ProgressData>,
+}
+
+impl Default for ReadProgress {
+    fn default() -> Self {
+        ReadProgress::load(get_progress_path())
+    }
+}
+
+impl ReadProgress {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        ReadProgress {
+            store: JsonStore::load("read progress", path),
+        }
+    }
+
+    pub fn manga(&self, manga_id: &str) -> HashMap<String, ChapterProgress> {
+        self.store
+            .lock()
+            .manga
+            .get(manga_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn mark(&self, manga_id: &str, chapter_ids: &[String], read: bool) -> Result<()> {
+        let mut data = self.store.lock();
+        mark_chapters(&mut data, manga_id, chapter_ids, read);
+
+        self.store.save(&data)
+    }
+
+    pub fn plan_sync(&self, manga_id: &str, remote_read: &[String]) -> MarkersSyncPlan {
+        let data = self.store.lock();
+        let empty = HashMap::new();
+        let local = data.manga.get(manga_id).unwrap_or(&empty);
+
+        plan_markers_sync(local, remote_read, data.synced_at.get(manga_id).copied())
+    }
+
+    /// Applies the pulled markers and records the sync time, once the pushed ones were sent.
+    pub fn finish_sync(&self, manga_id: &str, plan: &MarkersSyncPlan) -> Result<()> {
+        let mut data = self.store.lock();
+        if !plan.pull_read.is_empty() {
+            mark_chapters(&mut data, manga_id, &plan.pull_read, true);
+        }
+        if !plan.pull_unread.is_empty() {
+            mark_chapters(&mut data, manga_id, &plan.pull_unread, false);
+        }
+        data.synced_at.insert(manga_id.to_owned(), unix_now());
+
+        self.store.save(&data)
+    }
+
+    pub fn set_last_page(&self, manga_id: &str, chapter_id: &str, page: u32) -> Result<()> {
+        let mut data = self.store.lock();
+        let progress = data
+            .manga
+            .entry(manga_id.to_owned())
+            .or_default()
+            .entry(chapter_id.to_owned())
+            .or_default();
+
+        progress.last_page = Some(page);
+        progress.updated_at = unix_now();
+
+        self.store.save(&data)
+    }
+
+    pub fn next_unread<'a>(
+        &self,
+        manga_id: &str,
+        chapters: &[&'a ChapterAggregate],
+    ) -> Option<&'a ChapterAggregate> {
+        let progress = self.manga(manga_id);
+
+        find_next_unread(chapters, |chapter| is_read(&progress, chapter))
+    }
+
+    pub fn unread<'a>(
+        &self,
+        manga_id: &str,
+        chapters: &[&'a ChapterAggregate],
+    ) -> Vec<&'a ChapterAggregate> {
+        let progress = self.manga(manga_id);
+
+        chapters
+            .iter()
+            .filter(|chapter| !is_read(&progress, chapter))
+            .copied()
+            .collect()
+    }
+}
+
+fn mark_chapters(data: &mut ProgressData, manga_id: &str, chapter_ids: &[String], read: bool) {
+    let chapters = data.manga.entry(manga_id.to_owned()).or_default();
+
+    for chapter_id in chapter_ids {
+        if read {
+            let progress = chapters.entry(chapter_id.to_owned()).or_default();
+            progress.read = true;
+            progress.updated_at = unix_now();
+        } else if let Some(progress) = chapters.get_mut(chapter_id) {
+            // the last page is still worth keeping for a re-read
+            progress.read = false;
+            progress.updated_at = unix_now();
+        }
+    }
+
+    if chapters.is_empty() {
+        data.manga.remove(manga_id);
+    }
+}
+
+// a local marker changed after the last sync wins, otherwise MangaDex does;
+// without a previous sync local reads are pushed and remote ones pulled
+fn plan_markers_sync(
+    local: &HashMap<String, ChapterProgress>,
+    remote_read: &[String],
+    synced_at: Option<u64>,
+) -> MarkersSyncPlan {
+    let changed_locally = |progress: &ChapterProgress| match synced_at {
+        Some(synced_at) => progress.updated_at > synced_at,
+        None => progress.read,
+    };
+    let mut plan = MarkersSyncPlan::default();
+
+    for id in remote_read {
+        match local.get(id) {
+            Some(progress) if progress.read => {}
+            Some(progress) if changed_locally(progress) => plan.push_unread.push(id.clone()),
+            _ => plan.pull_read.push(id.clone()),
+        }
+    }
+
+    let mut local_read: Vec<(&String, &ChapterProgress)> = local
+        .iter()
+        .filter(|(id, progress)| progress.read && !remote_read.contains(id))
+        .collect();
+    local_read.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (id, progress) in local_read {
+        if changed_locally(progress) {
+            plan.push_read.push(id.clone());
+        } else {
+            plan.pull_unread.push(id.clone());
+        }
+    }
+
+    plan
+}
+
+fn is_read(progress: &HashMap<String, ChapterProgress>, chapter: &ChapterAggregate) -> bool {
+    chapter
+        .ids()
+        .any(|id| progress.get(id).map_or(false, |p| p.read))
+}
+
+// first unread chapter after the furthest read one, wrapping to skipped chapters
+fn find_next_unread<'a>(
+    chapters: &[&'a ChapterAggregate],
+    is_read: impl Fn(&ChapterAggregate) -> bool,
+) -> Option<&'a ChapterAggregate> {
+    let start = chapters
+        .iter()
+        .rposition(|chapter| is_read(chapter))
+        .map_or(0, |index| index + 1);
+
+    chapters[start..]
+        .iter()
+        .chain(chapters[..start].iter())
+        .find(|chapter| !is_read(chapter))
+        .copied()
+}
+
+fn get_progress_path() -> Option<PathBuf> {
+    tauri::api::path::data_dir().map(|dir| dir.join(APP_DIR_NAME).join(PROGRESS_FILE))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn chapter(id: &str) -> ChapterAggregate {
+        ChapterAggregate {
+            id: id.to_string(),
+            chapter: id.to_string(),
+            others: vec![],
+        }
+    }
+
+    #[test]
+    fn next_after_last_read() {
+        let chapters = [chapter("1"), chapter("2"), chapter("3"), chapter("4")];
+        let refs: Vec<&ChapterAggregate> = chapters.iter().collect();
+        let read = ["1", "3"];
+
+        let next = find_next_unread(&refs, |c| read.contains(&c.id.as_str()));
+
+        assert_eq!(next.unwrap().id, "4");
+    }
+
+    #[test]
+    fn wraps_to_skipped_chapter() {
+        let chapters = [chapter("1"), chapter("2"), chapter("3")];
+        let refs: Vec<&ChapterAggregate> = chapters.iter().collect();
+        let read = ["1", "3"];
+
+        let next = find_next_unread(&refs, |c| read.contains(&c.id.as_str()));
+
+        assert_eq!(next.unwrap().id, "2");
+    }
+
+    #[test]
+    fn nothing_left_to_read() {
+        let chapters = [chapter("1")];
+        let refs: Vec<&ChapterAggregate> = chapters.iter().collect();
+
+        assert!(find_next_unread(&refs, |_| true).is_none());
+        assert_eq!(find_next_unread(&refs, |_| false).unwrap().id, "1");
+    }
+
+    fn progress(read: bool, updated_at: u64) -> ChapterProgress {
+        ChapterProgress {
+            read,
+            last_page: None,
+            updated_at,
+        }
+    }
+
+    #[test]
+    fn syncs_markers_both_ways() {
+        let local = HashMap::from([
+            ("unmarked-locally".to_string(), progress(false, 20)),
+            ("unmarked-remotely".to_string(), progress(true, 5)),
+            ("read-locally".to_string(), progress(true, 20)),
+            ("old-page".to_string(), progress(false, 5)),
+            ("both".to_string(), progress(true, 5)),
+        ]);
+        let remote: Vec<String> = ["unmarked-locally", "old-page", "read-remotely", "both"]
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+
+        let plan = plan_markers_sync(&local, &remote, Some(10));
+
+        assert_eq!(plan.pull_read, ["old-page", "read-remotely"]);
+        assert_eq!(plan.pull_unread, ["unmarked-remotely"]);
+        assert_eq!(plan.push_read, ["read-locally"]);
+        assert_eq!(plan.push_unread, ["unmarked-locally"]);
+    }
+
+    #[test]
+    fn first_sync_keeps_all_reads() {
+        let local = HashMap::from([
+            ("local".to_string(), progress(true, 5)),
+            ("page-only".to_string(), progress(false, 5)),
+        ]);
+        let remote = vec!["page-only".to_string()];
+
+        let plan = plan_markers_sync(&local, &remote, None);
+
+        assert_eq!(plan.pull_read, ["page-only"]);
+        assert_eq!(plan.push_read, ["local"]);
+        assert!(plan.pull_unread.is_empty());
+        assert!(plan.push_unread.is_empty());
+    }
+
+    #[test]
+    fn keeps_last_page_of_unmarked_chapters() {
+        let progress = ReadProgress::load(None);
+
+        progress
+            .mark("manga", &["a".to_string(), "b".to_string()], true)
+            .unwrap();
+        progress.set_last_page("manga", "b", 12).unwrap();
+        progress.mark("manga", &["b".to_string()], false).unwrap();
+        progress.set_last_page("manga", "c", 5).unwrap();
+
+        let chapters = progress.manga("manga");
+
+        assert!(chapters["a"].read);
+        assert!(!chapters["b"].read);
+        assert_eq!(chapters["b"].last_page, Some(12));
+        assert_eq!(chapters["c"].last_page, Some(5));
+        assert!(!chapters["c"].read);
+    }
+}
@@ -0,0 +1,369 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+
+use log::{debug, error};
+use reqwest::Url;
+use serde::Serialize;
+use tauri::http::{Request, Response, ResponseBuilder};
+use tauri::{AppHandle, Manager};
+use zip::ZipArchive;
+
+use crate::model::{Result, ServiceError};
+
+pub const READER_PROTOCOL: &str = "reader";
+
+const PREFETCH_PAGES: usize = 3;
+const CACHED_PAGES: usize = 16;
+const IMAGE_EXTENSIONS: [&str; 7] = ["jpg", "jpeg", "png", "gif", "webp", "avif", "bmp"];
+
+#[derive(Default)]
+pub struct Reader {
+    archives: Mutex<HashMap<String, Arc<Archive>>>,
+    next_id: AtomicU64,
+}
+
+struct Archive {
+    path: PathBuf,
+    pages: Vec<String>,
+    zip: Mutex<ZipArchive<File>>,
+    cache: Mutex<VecDeque<(usize, Arc<Vec<u8>>)>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveView {
+    id: String,
+    title: String,
+    path: PathBuf,
+    pages: Vec<PageView>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PageView {
+    index: usize,
+    name: String,
+    url: String,
+}
+
+impl PageView {
+    pub fn new(index: usize, name: &str, url: String) -> Self {
+        PageView {
+            index,
+            name: name.to_owned(),
+            url,
+        }
+    }
+}
+
+impl Reader {
+    pub fn open(&self, path: &Path) -> Result<ArchiveView> {
+        let is_archive = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.eq_ignore_ascii_case("cbz") || ext.eq_ignore_ascii_case("zip"))
+            .unwrap_or(false);
+
+        if !is_archive {
+            return Err(ServiceError::InvalidArguments(format!(
+                "{} is not a cbz archive",
+                path.display()
+            )));
+        }
+
+        let zip = ZipArchive::new(File::open(path)?)?;
+        let mut pages: Vec<String> = zip
+            .file_names()
+            .filter(|name| is_image(name))
+            .map(str::to_owned)
+            .collect();
+        pages.sort_by(|a, b| natural_cmp(a, b));
+
+        let id = self
+            .next_id
+            .fetch_add(1, AtomicOrdering::Relaxed)
+            .to_string();
+        let archive = Arc::new(Archive {
+            path: path.to_owned(),
+            pages,
+            zip: Mutex::new(zip),
+            cache: Mutex::new(VecDeque::with_capacity(CACHED_PAGES)),
+        });
+
+        debug!("opened archive {id}: {}", path.display());
+
+        let view = archive.view(&id);
+        self.lock().insert(id, archive);
+
+        Ok(view)
+    }
+
+    pub fn close(&self, id: &str) {
+        self.lock().remove(id);
+    }
+
+    pub fn pages(&self, id: &str) -> Result<Vec<PageView>> {
+        Ok(self.get(id)?.view(id).pages)
+    }
+
+    pub fn read_page(&self, id: &str, index: usize) -> Result<(String, Arc<Vec<u8>>)> {
+        let archive = self.get(id)?;
+        let name = archive
+            .pages
+            .get(index)
+            .ok_or_else(|| ServiceError::InvalidArguments(format!("page {index} not found")))?
+            .to_owned();
+
+        Ok((name, archive.read(index)?))
+    }
+
+    pub fn prefetch(&self, id: &str, from: usize, count: usize) -> Result<()> {
+        let archive = self.get(id)?;
+        let to = archive.pages.len().min(from.saturating_add(count));
+
+        for index in from..to {
+            archive.read(index)?;
+        }
+
+        Ok(())
+    }
+
+    fn get(&self, id: &str) -> Result<Arc<Archive>> {
+        self.lock()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| ServiceError::InvalidArguments(format!("archive {id} is not open")))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Arc<Archive>>> {
+        self.archives.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Archive {
+    fn view(&self, id: &str) -> ArchiveView {
+        let title = self
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let pages = self
+            .pages
+            .iter()
+            .enumerate()
+            .map(|(index, name)| PageView::new(index, name, page_url(id, index)))
+            .collect();
+
+        ArchiveView {
+            id: id.to_owned(),
+            title,
+            path: self.path.to_owned(),
+            pages,
+        }
+    }
+
+    fn read(&self, index: usize) -> Result<Arc<Vec<u8>>> {
+        if let Some(data) = self.cached(index) {
+            return Ok(data);
+        }
+
+        let mut data = Vec::new();
+        {
+            let mut zip = self.zip.lock().unwrap_or_else(|e| e.into_inner());
+            zip.by_name(&self.pages[index])?.read_to_end(&mut data)?;
+        }
+
+        let data = Arc::new(data);
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        if cache.len() == CACHED_PAGES {
+            cache.pop_front();
+        }
+        cache.push_back((index, data.clone()));
+
+        Ok(data)
+    }
+
+    fn cached(&self, index: usize) -> Option<Arc<Vec<u8>>> {
+        let cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache
+            .iter()
+            .find(|(cached, _)| *cached == index)
+            .map(|(_, data)| data.clone())
+    }
+}
+
+pub fn handle_protocol(
+    app: &AppHandle,
+    request: &Request,
+) -> std::result::Result<Response, Box<dyn Error>> {
+    let (id, index) = match parse_page_uri(request.uri()) {
+        Some(page) => page,
+        None => return ResponseBuilder::new().status(400).body(Vec::new()),
+    };
+
+    let reader = app.state::<Reader>();
+    let (name, data) = match reader.read_page(&id, index) {
+        Ok(page) => page,
+        Err(e) => {
+            error!("failed to read page {index} of archive {id}: {e}");
+            return ResponseBuilder::new().status(404).body(Vec::new());
+        }
+    };
+
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Err(e) = app
+            .state::<Reader>()
+            .prefetch(&id, index + 1, PREFETCH_PAGES)
+        {
+            debug!("failed to prefetch pages of archive {id}: {e}");
+        }
+    });
+
+    ResponseBuilder::new()
+        .status(200)
+        .mimetype(mime_type(&name))
+        .body(data.to_vec())
+}
+
+pub fn page_url(id: &str, index: usize) -> String {
+    protocol_url(READER_PROTOCOL, id, index)
+}
+
+pub fn protocol_url(protocol: &str, id: &str, index: usize) -> String {
+    if cfg!(windows) {
+        format!("https://{protocol}.localhost/{id}/{index}")
+    } else {
+        format!("{protocol}://localhost/{id}/{index}")
+    }
+}
+
+pub fn parse_page_uri(uri: &str) -> Option<(String, usize)> {
+    let url = Url::parse(uri).ok()?;
+    let mut segments = url.path_segments()?;
+    let id = segments.next()?.to_owned();
+    let index = segments.next()?.parse().ok()?;
+
+    Some((id, index))
+}
+
+fn is_image(name: &str) -> bool {
+    let ext = match name.rsplit_once('.') {
+        Some((_, ext)) => ext.to_lowercase(),
+        None => return false,
+    };
+
+    !name.ends_with('/') && IMAGE_EXTENSIONS.contains(&ext.as_str())
+}
+
+pub fn mime_type(name: &str) -> &'static str {
+    let ext = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_lowercase())
+        .unwrap_or_default();
+
+    match ext.as_str() {
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "bmp" => "image/bmp",
+        _ => "image/jpeg",
+    }
+}
+
+/// Compares strings treating digit runs as numbers, so `2.jpg` < `10.jpg`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a_char), Some(b_char)) if a_char.is_ascii_digit() && b_char.is_ascii_digit() => {
+                let a_num = take_number(&mut a_chars);
+                let b_num = take_number(&mut b_chars);
+                let a_trimmed = a_num.trim_start_matches('0');
+                let b_trimmed = b_num.trim_start_matches('0');
+
+                let ordering = a_trimmed
+                    .len()
+                    .cmp(&b_trimmed.len())
+                    .then_with(|| a_trimmed.cmp(b_trimmed));
+
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(a_char), Some(b_char)) => {
+                let ordering = a_char.to_lowercase().cmp(b_char.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut number = String::new();
+
+    while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+        number.push(*c);
+        chars.next();
+    }
+
+    number
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn natural_order() {
+        let mut names = vec!["10.jpg", "2.jpg", "1.jpg", "cover.jpg", "01a.png", "1b.png"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+
+        assert_eq!(
+            names,
+            vec!["1.jpg", "01a.png", "1b.png", "2.jpg", "10.jpg", "cover.jpg"]
+        );
+    }
+
+    #[test]
+    fn natural_order_is_case_insensitive() {
+        assert_eq!(natural_cmp("Page2.jpg", "page10.jpg"), Ordering::Less);
+    }
+
+    #[test]
+    fn parses_page_uri() {
+        assert_eq!(
+            parse_page_uri("reader://localhost/3/12"),
+            Some(("3".to_string(), 12))
+        );
+        assert_eq!(
+            parse_page_uri("https://reader.localhost/3/12"),
+            Some(("3".to_string(), 12))
+        );
+        assert_eq!(parse_page_uri("reader://localhost/3"), None);
+    }
+
+    #[test]
+    fn filters_images() {
+        assert!(is_image("01.JPG"));
+        assert!(!is_image("ComicInfo.xml"));
+        assert!(!is_image("folder/"));
+    }
+}
@@ -0,0 +1,137 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::stream::{self, StreamExt};
+use log::{debug, warn};
+use reqwest::{Response, Url};
+use serde::Serialize;
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+
+use crate::constants::MANGADEX_REPORT_URL;
+use crate::http;
+
+const QUEUE_SIZE: usize = 1024;
+const BATCH_SIZE: usize = 32;
+const CONCURRENT_REPORTS: usize = 4;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static QUEUE: Mutex<Option<Sender<FrameReport>>> = Mutex::new(None);
+
+/// Delivery report for an image fetched from a MangaDex@Home server.
+#[derive(Debug, Serialize)]
+pub struct FrameReport {
+    url: String,
+    success: bool,
+    cached: bool,
+    bytes: usize,
+    duration: u128,
+}
+
+impl FrameReport {
+    pub fn new(url: &str, success: bool, cached: bool, bytes: usize, duration: Duration) -> Self {
+        FrameReport {
+            url: url.to_owned(),
+            success,
+            cached,
+            bytes,
+            duration: duration.as_millis(),
+        }
+    }
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_cache_hit(response: &Response) -> bool {
+    response
+        .headers()
+        .get("X-Cache")
+        .and_then(|cache| cache.to_str().ok())
+        .map_or(false, |cache| cache.contains("HIT"))
+}
+
+/// Queues the report without waiting for it to be sent, a full queue or a
+/// failed report never affects the download itself.
+pub fn report(report: FrameReport) {
+    if !ENABLED.load(Ordering::Relaxed) || !needs_report(&report.url) {
+        return;
+    }
+
+    let mut queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+    let sender = queue.get_or_insert_with(|| {
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        tauri::async_runtime::spawn(send_reports(receiver));
+        sender
+    });
+
+    match sender.try_send(report) {
+        Ok(()) => {}
+        Err(TrySendError::Full(report)) => debug!("report queue full, dropping {}", report.url),
+        Err(TrySendError::Closed(_)) => *queue = None,
+    }
+}
+
+// uploads.mangadex.org is not part of the network and takes no reports
+fn needs_report(url: &str) -> bool {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| !host.contains("mangadex.org")))
+        .unwrap_or(false)
+}
+
+// the endpoint takes a single report per request, so whatever piled up is
+// drained and posted a few at a time
+async fn send_reports(mut receiver: Receiver<FrameReport>) {
+    while let Some(first) = receiver.recv().await {
+        let mut batch = vec![first];
+        while batch.len() < BATCH_SIZE {
+            match receiver.try_recv() {
+                Ok(report) => batch.push(report),
+                Err(_) => break,
+            }
+        }
+
+        let client = match http::client() {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("dropping {} reports: {e}", batch.len());
+                continue;
+            }
+        };
+        stream::iter(batch)
+            .for_each_concurrent(CONCURRENT_REPORTS, |report| {
+                let client = &client;
+                async move {
+                    let result = client
+                        .post(MANGADEX_REPORT_URL)
+                        .json(&report)
+                        .send()
+                        .await
+                        .and_then(Response::error_for_status);
+
+                    if let Err(e) = result {
+                        warn!("failed to report {}: {e}", report.url);
+                    }
+                }
+            })
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn skips_reports_for_mangadex_hosts() {
+        assert!(needs_report(
+            "https://abc.xyz.mangadex.network:443/token/data/hash/1.png"
+        ));
+        assert!(!needs_report(
+            "https://uploads.mangadex.org/data/hash/1.png"
+        ));
+        assert!(!needs_report("not a url"));
+    }
+}
@@ -1,22 +1,41 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ffi::OsStr;
 use std::fs;
-use std::io::{Read, Write};
+use std::future::Future;
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
-use async_recursion::async_recursion;
+use bytes::Bytes;
+use futures::future::{self, FutureExt};
 use futures::stream::{self, StreamExt};
-use log::{debug, error, info};
-use reqwest::{Response, Url};
-use serde::Serialize;
+use log::{debug, error, info, warn};
+use reqwest::Url;
 use thiserror::Error;
-use zip::write::FileOptions;
 
-use crate::constants::{MANGADEX_API, MANGADEX_REPORT_URL, MAX_FRAME_RETRIES};
+use crate::archive::ArchiveWriter;
+use crate::auth::Auth;
+use crate::cache;
+use crate::collision::{resolve_archive_path, CollisionPolicy, ReleaseInfo};
+use crate::constants::{
+    COVERS_CACHE_TTL, COVERS_PAGE_SIZE, COVER_QUALITY, FEED_CACHE_TTL, FRAME_RETRY_DELAY,
+    MANGADEX_API, MANGADEX_UPLOADS, MANGA_CACHE_TTL, MAX_FRAME_RETRIES, MAX_FRAME_RETRY_DELAY,
+    MAX_SERVER_ROTATIONS, SEARCH_CACHE_TTL, STATISTICS_CACHE_TTL,
+};
+use crate::devices::{ArchiveFormat, DeviceProfile};
+use crate::epub::EpubBook;
+use crate::http;
+use crate::library::{self, ArchiveInfo, Library, LibraryEntry, ScanReport};
+use crate::limits::{DownloadToken, RequestLimiter};
+use crate::processing::{self, ChapterProcessor, OutputProfile};
+use crate::progress::ReadProgress;
+use crate::reports::{self, FrameReport};
 
 use crate::model::{
-    AggregateResponse, ApiResponse, AtHomeResponse, ChapterProps, ChaptersResponse, FeedData,
-    Manga, MangaData, MangaStatistics, MangaView, ResponseError, Result, ServiceError,
+    AggregateResponse, ApiResponse, AtHomeResponse, ChapterAggregate, ChapterAvailability,
+    ChapterProps, ChaptersResponse, CoverData, DownloadReport, DownloadStatus, FeedData,
+    FollowedManga, Locales, Manga, MangaData, MangaStatistics, MangaView, ReadMarkersResponse,
+    ReadMarkersSync, ReadMarkersUpdate, ReadingStatusResponse, ResponseError, Result, ServiceError,
     StatisticsResponse,
 };
 
@@ -32,28 +51,48 @@ pub enum SearchError {
     ApiErrors(Vec<ResponseError>),
 }
 
-pub async fn search(query: &str) -> Result<Vec<MangaView>> {
+pub async fn search(query: &str, locales: &Locales) -> Result<Vec<MangaView>> {
     if query.is_empty() {
         return Err(ServiceError::InvalidArguments("query is empty".to_owned()));
     }
 
-    let search_url = format!("{MANGADEX_API}/manga?title={query}&includes[]=cover_art&limit=5");
-    let res: ApiResponse<Vec<MangaData>> = reqwest::get(search_url).await?.json().await?;
-    let result = res.result("search")?;
+    Ok(search_manga(query)
+        .await?
+        .iter()
+        .map(|data| MangaView::new(data, locales))
+        .collect())
+}
+
+async fn search_manga(query: &str) -> Result<Vec<MangaData>> {
+    let search_url = get_search_url(query)?;
+    let res: ApiResponse<Vec<MangaData>> = cache::get_json(&search_url, SEARCH_CACHE_TTL).await?;
+
+    res.result("search")
+}
+
+// titles taken from file names may hold `&`, `#` and the like
+fn get_search_url(query: &str) -> Result<String> {
+    let mut url = Url::parse(&format!(
+        "{MANGADEX_API}/manga?includes[]=cover_art&limit=5"
+    ))
+    .map_err(|e| ServiceError::Internal(e.to_string()))?;
+    url.query_pairs_mut().append_pair("title", query);
 
-    Ok(result.iter().map(MangaView::from).collect())
+    Ok(url.into())
 }
 
-pub async fn get_manga(id: &str) -> Result<Manga> {
+pub async fn get_manga(id: &str, locales: &Locales) -> Result<Manga> {
     let manga_data = fetch_manga_data(id).await?;
     let stats = fetch_statistitcs(id).await?;
 
-    Ok(Manga::new(&manga_data, &stats))
+    Ok(Manga::new(&manga_data, &stats, locales))
 }
 
 async fn fetch_manga_data(id: &str) -> Result<MangaData> {
-    let manga_url = format!("{MANGADEX_API}/manga/{id}?includes[]=author&includes[]=cover_art");
-    let manga_res: ApiResponse<MangaData> = reqwest::get(manga_url).await?.json().await?;
+    let manga_url = format!(
+        "{MANGADEX_API}/manga/{id}?includes[]=author&includes[]=artist&includes[]=cover_art"
+    );
+    let manga_res: ApiResponse<MangaData> = cache::get_json(&manga_url, MANGA_CACHE_TTL).await?;
     let manga = manga_res.result("fetch_manga")?;
 
     debug!("Got manga model: {:#?}", manga);
@@ -63,8 +102,11 @@ async fn fetch_manga_data(id: &str) -> Result<MangaData> {
 
 async fn fetch_statistitcs(id: &str) -> Result<MangaStatistics> {
     let statistics_url = format!("{MANGADEX_API}/statistics/manga/{id}");
-    let mut statistics_res: StatisticsResponse = reqwest::get(statistics_url).await?.json().await?;
-    let stats = statistics_res.statistics.remove(id).unwrap();
+    let mut statistics_res: StatisticsResponse =
+        cache::get_json(&statistics_url, STATISTICS_CACHE_TTL).await?;
+    let stats = statistics_res.statistics.remove(id).ok_or_else(|| {
+        ServiceError::missing_data("statistics", &format!("no statistics for {id}"))
+    })?;
 
     debug!("Got statistics for {id}: {:#?}", stats);
 
@@ -73,7 +115,7 @@ async fn fetch_statistitcs(id: &str) -> Result<MangaStatistics> {
 
 pub async fn fetch_feed(id: &str, lang: &str, limit: u32, offset: u32) -> Result<ChaptersResponse> {
     let feed_url = format!("{MANGADEX_API}/manga/{id}/feed?limit={limit}&offset={offset}&translatedLanguage[]={lang}&includes[]=scanlation_group&order[volume]=asc&order[chapter]=asc");
-    let res: ApiResponse<Vec<FeedData>> = reqwest::get(feed_url).await?.json().await?;
+    let res: ApiResponse<Vec<FeedData>> = cache::get_json(&feed_url, FEED_CACHE_TTL).await?;
     let response: ChaptersResponse = res.try_into()?;
 
     Ok(response)
@@ -81,240 +123,1012 @@ pub async fn fetch_feed(id: &str, lang: &str, limit: u32, offset: u32) -> Result
 
 pub async fn aggregate(id: &str, lang: &str) -> Result<AggregateResponse> {
     let aggregate_url = format!("{MANGADEX_API}/manga/{id}/aggregate?translatedLanguage[]={lang}");
-    let res: AggregateResponse = reqwest::get(aggregate_url).await?.json().await?;
+    let res: AggregateResponse = cache::get_json(&aggregate_url, FEED_CACHE_TTL).await?;
 
     Ok(res)
 }
 
-pub async fn download(chapters: Vec<ChapterProps>) -> Result<()> {
+pub async fn next_unread_chapter(
+    progress: &ReadProgress,
+    manga_id: &str,
+    lang: &str,
+) -> Result<Option<ChapterAggregate>> {
+    let aggregated = aggregate(manga_id, lang).await?;
+    let chapters = aggregated.ordered_chapters();
+
+    Ok(progress.next_unread(manga_id, &chapters).cloned())
+}
+
+pub async fn unread_chapters(
+    progress: &ReadProgress,
+    manga_id: &str,
+    lang: &str,
+) -> Result<Vec<ChapterAggregate>> {
+    let aggregated = aggregate(manga_id, lang).await?;
+    let chapters = aggregated.ordered_chapters();
+
+    Ok(progress
+        .unread(manga_id, &chapters)
+        .into_iter()
+        .cloned()
+        .collect())
+}
+
+pub async fn fetch_follows(auth: &Auth, locales: &Locales) -> Result<Vec<FollowedManga>> {
+    const LIMIT: u32 = 100;
+
+    let statuses: ReadingStatusResponse = auth.get(&format!("{MANGADEX_API}/manga/status")).await?;
+    let mut follows = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let follows_url = format!(
+            "{MANGADEX_API}/user/follows/manga?limit={LIMIT}&offset={offset}&includes[]=cover_art"
+        );
+        let res: ApiResponse<Vec<MangaData>> = auth.get(&follows_url).await?;
+        let total = res.total.unwrap_or(0);
+        let page = res.result("follows")?;
+
+        offset += LIMIT;
+        let is_last = page.is_empty() || offset >= total;
+
+        follows.extend(page.iter().map(|data| FollowedManga {
+            view: MangaView::new(data, locales),
+            reading_status: statuses.statuses.get(&data.id).cloned(),
+        }));
+
+        if is_last {
+            break;
+        }
+    }
+
+    debug!("imported {} followed manga", follows.len());
+
+    Ok(follows)
+}
+
+pub async fn sync_read_markers(
+    auth: &Auth,
+    progress: &ReadProgress,
+    manga_id: &str,
+) -> Result<ReadMarkersSync> {
+    let read_url = format!("{MANGADEX_API}/manga/{manga_id}/read");
+    let remote: ReadMarkersResponse = auth.get(&read_url).await?;
+    let plan = progress.plan_sync(manga_id, &remote.data);
+
+    if !plan.push_read.is_empty() || !plan.push_unread.is_empty() {
+        let update = ReadMarkersUpdate {
+            chapter_ids_read: plan.push_read.clone(),
+            chapter_ids_unread: plan.push_unread.clone(),
+        };
+        auth.post(&read_url, &update).await?;
+    }
+
+    progress.finish_sync(manga_id, &plan)?;
+
+    Ok(ReadMarkersSync {
+        pulled: plan.pull_read.len() + plan.pull_unread.len(),
+        pushed: plan.push_read.len() + plan.push_unread.len(),
+    })
+}
+
+pub async fn fetch_covers(manga_id: &str) -> Result<Vec<CoverData>> {
+    let mut covers = Vec::new();
+
+    loop {
+        let covers_url = format!(
+            "{MANGADEX_API}/cover?manga[]={manga_id}&limit={COVERS_PAGE_SIZE}&offset={}&order[volume]=asc",
+            covers.len()
+        );
+        let res: ApiResponse<Vec<CoverData>> =
+            cache::get_json(&covers_url, COVERS_CACHE_TTL).await?;
+        let total = res.total.unwrap_or(0) as usize;
+        let page = res.result("covers")?;
+
+        let done = page.is_empty() || covers.len() + page.len() >= total;
+        covers.extend(page);
+        if done {
+            return Ok(covers);
+        }
+    }
+}
+
+pub async fn scan_library(
+    library: &Library,
+    folders: Vec<PathBuf>,
+    lang: &str,
+    locales: &Locales,
+) -> Result<ScanReport> {
+    let mut report = ScanReport {
+        removed: library.prune()?,
+        ..Default::default()
+    };
+
+    let archives = tauri::async_runtime::spawn_blocking(move || {
+        library::find_archives(&folders)
+            .iter()
+            .map(|path| library::read_archive_info(path))
+            .collect::<Vec<ArchiveInfo>>()
+    })
+    .await
+    .map_err(|e| ServiceError::Internal(e.to_string()))?;
+
+    let mut series_matches: HashMap<String, Option<String>> = HashMap::new();
+    let mut aggregates: HashMap<String, AggregateResponse> = HashMap::new();
+    let mut entries = Vec::new();
+
+    for info in archives {
+        if info
+            .chapter_id
+            .as_deref()
+            .map_or(false, |id| library.contains(id))
+        {
+            report.known += 1;
+            continue;
+        }
+
+        let manga_id = match (&info.manga_id, &info.series) {
+            (Some(manga_id), _) => Some(manga_id.to_owned()),
+            (None, Some(series)) => match series_matches.get(series) {
+                Some(manga_id) => manga_id.clone(),
+                None => {
+                    let manga_id = match_series(series, locales).await;
+                    series_matches.insert(series.to_owned(), manga_id.clone());
+                    manga_id
+                }
+            },
+            (None, None) => None,
+        };
+
+        let manga_id = match manga_id {
+            Some(manga_id) => manga_id,
+            None => {
+                report.unmatched.push(info.path);
+                continue;
+            }
+        };
+
+        if !aggregates.contains_key(&manga_id) {
+            match aggregate(&manga_id, lang).await {
+                Ok(aggregated) => {
+                    aggregates.insert(manga_id.to_owned(), aggregated);
+                }
+                Err(e) => {
+                    error!("Failed to get chapters of {manga_id}: {e}");
+                    report.unmatched.push(info.path);
+                    continue;
+                }
+            }
+        }
+
+        let chapter = aggregates[&manga_id]
+            .ordered_chapters()
+            .into_iter()
+            .find(|chapter| match (&info.chapter_id, &info.chapter) {
+                (Some(chapter_id), _) => chapter.ids().any(|id| id == chapter_id),
+                (None, Some(number)) => library::normalize_number(&chapter.chapter) == *number,
+                (None, None) => false,
+            })
+            .map(|chapter| {
+                info.chapter_id
+                    .clone()
+                    .unwrap_or_else(|| chapter.id.clone())
+            });
+
+        match chapter {
+            Some(chapter_id) if library.contains(&chapter_id) => report.known += 1,
+            Some(chapter_id) => entries.push(LibraryEntry::new(&manga_id, &chapter_id, info.path)),
+            None => report.unmatched.push(info.path),
+        }
+    }
+
+    report.imported = entries.len();
+    library.register(entries)?;
+
+    Ok(report)
+}
+
+// search hit with a title or alternate title that is exactly the series name,
+// any other hit could be an unrelated series and leaves the archive unmatched
+async fn match_series(series: &str, locales: &Locales) -> Option<String> {
+    let results = match search_manga(series).await {
+        Ok(results) => results,
+        Err(e) => {
+            error!("Failed to search for {series}: {e}");
+            return None;
+        }
+    };
+
+    results
+        .iter()
+        .find(|manga| is_series_title(manga, series, locales))
+        .map(|manga| manga.id.to_owned())
+}
+
+fn is_series_title(manga: &MangaData, series: &str, locales: &Locales) -> bool {
+    let attributes = &manga.attributes;
+    let title = MangaView::new(manga, locales).title().to_owned();
+    let alt_titles = locales.sort_alt_titles(
+        &attributes.alt_titles,
+        attributes.original_language.as_deref(),
+    );
+
+    std::iter::once(title)
+        .chain(alt_titles)
+        .any(|title| title.trim().eq_ignore_ascii_case(series))
+}
+
+pub async fn download(
+    library: &Library,
+    limiter: &RequestLimiter,
+    chapters: Vec<ChapterProps>,
+    profile: OutputProfile,
+    device: Option<DeviceProfile>,
+    collision: CollisionPolicy,
+) -> Result<DownloadReport> {
+    let token = limiter.download_token();
+    let extension = match &device {
+        Some(device) => device.archive.extension(),
+        None => ArchiveFormat::Cbz.extension(),
+    };
+
+    let mut report = DownloadReport::default();
+    let mut reserved = HashSet::new();
+    let chapters: Vec<(ChapterProps, PathBuf)> = chapters
+        .into_iter()
+        .filter_map(|chapter| {
+            if let Some(url) = &chapter.external_url {
+                report.add(
+                    &chapter.id,
+                    DownloadStatus::External {
+                        url: url.to_owned(),
+                    },
+                );
+                return None;
+            }
+            // known to have no pages, so neither at-home nor the disk is touched
+            if chapter.availability == ChapterAvailability::Unavailable {
+                report.add(&chapter.id, DownloadStatus::Unavailable);
+                return None;
+            }
+
+            let path = match collision {
+                CollisionPolicy::Skip if library.contains(&chapter.id) => None,
+                _ => match get_archive_path(&chapter, extension) {
+                    Ok(path) => {
+                        let release = ReleaseInfo::new(&chapter, &profile);
+                        resolve_archive_path(path, extension, &release, collision, &mut reserved)
+                    }
+                    Err(e) => {
+                        report.add(&chapter.id, DownloadStatus::failed(e));
+                        return None;
+                    }
+                },
+            };
+            if path.is_none() {
+                report.add(&chapter.id, DownloadStatus::Skipped);
+            }
+
+            Some((chapter, path?))
+        })
+        .collect();
+
+    let client = http::client()?;
+    let covers = download_covers(&client, chapters.iter().map(|(chapter, _)| chapter)).await;
+
     let stream = stream::iter(chapters)
-        .map(download_chapter)
-        .buffer_unordered(100);
+        .map(|(chapter, archive_path)| {
+            let cover = chapter.manga_id.as_ref().and_then(|manga_id| {
+                let volume = chapter.volume.as_ref()?;
+                covers
+                    .get(&(manga_id.to_owned(), volume.to_owned()))
+                    .cloned()
+            });
+
+            let ids = (chapter.manga_id.clone(), chapter.id.clone());
+            let profile = profile.clone();
+            let device = device.as_ref();
+
+            async move {
+                let result = match limiter.wait_for_window(token).await {
+                    Ok(()) => {
+                        download_chapter(
+                            chapter,
+                            archive_path,
+                            cover,
+                            profile,
+                            device,
+                            limiter,
+                            token,
+                        )
+                        .await
+                    }
+                    Err(e) => Err(e),
+                };
+                (ids, result)
+            }
+        })
+        .buffer_unordered(limiter.settings().chapters);
 
     stream
-        .for_each(|e| async {
-            match e {
-                Ok(_) => info!("Successfully downloaded some chapter"),
-                Err(e) => error!("Failed to download {e}"),
+        .for_each(|((manga_id, chapter_id), result)| {
+            let status = match result {
+                Ok(DownloadStatus::Downloaded { path }) => {
+                    info!("Successfully downloaded {}", path.display());
+
+                    if let Some(manga_id) = manga_id {
+                        let entry = LibraryEntry::new(&manga_id, &chapter_id, path.clone());
+                        if let Err(e) = library.register(vec![entry]) {
+                            error!("Failed to add {chapter_id} to library: {e}");
+                        }
+                    }
+
+                    DownloadStatus::Downloaded { path }
+                }
+                Ok(status) => {
+                    info!("Skipped {chapter_id}: {status:?}");
+                    status
+                }
+                Err(e) => {
+                    error!("Failed to download {e}");
+                    DownloadStatus::failed(e)
+                }
             };
+            report.add(&chapter_id, status);
+
+            future::ready(())
         })
         .await;
 
-    Ok(())
+    Ok(report)
 }
 
-pub async fn download_chapter(chapter: ChapterProps) -> Result<()> {
-    const CONCURRENT_FRAMES: usize = 30;
+#[derive(Clone)]
+struct Cover {
+    data: Bytes,
+    extension: String,
+}
 
-    let chapter_id = chapter.id;
-    let chapter_name = chapter.fullname;
-    let at_home = get_at_home(&chapter_id).await?;
+async fn download_covers<'a>(
+    client: &reqwest::Client,
+    chapters: impl Iterator<Item = &'a ChapterProps>,
+) -> HashMap<(String, String), Cover> {
+    let mut series: BTreeMap<&str, (Option<&str>, BTreeSet<&str>)> = BTreeMap::new();
+
+    for chapter in chapters {
+        if let Some(manga_id) = chapter.manga_id.as_deref() {
+            let (manga_name, volumes) = series.entry(manga_id).or_default();
+            *manga_name = manga_name.or(chapter.manga_name.as_deref());
+            volumes.extend(chapter.volume.as_deref());
+        }
+    }
 
-    let base_url = at_home.base_url;
-    let hash = at_home.chapter.hash;
+    let mut covers = HashMap::new();
 
-    let client = reqwest::Client::new();
-    let chapter_path_buf = get_chapter_path(&chapter_name);
-    let chapter_path = chapter_path_buf.as_path();
+    for (manga_id, (manga_name, volumes)) in series {
+        match download_series_covers(client, manga_id, manga_name, &volumes).await {
+            Ok(series_covers) => covers.extend(
+                series_covers
+                    .into_iter()
+                    .map(|(volume, cover)| ((manga_id.to_owned(), volume), cover)),
+            ),
+            Err(e) => error!("Failed to download covers for {manga_id}: {e}"),
+        }
+    }
+
+    covers
+}
+
+async fn download_series_covers(
+    client: &reqwest::Client,
+    manga_id: &str,
+    manga_name: Option<&str>,
+    volumes: &BTreeSet<&str>,
+) -> Result<HashMap<String, Cover>> {
+    let cover_data = fetch_covers(manga_id).await?;
+    let mut covers: HashMap<String, Cover> = HashMap::new();
+
+    for data in &cover_data {
+        let volume = match data.attributes.volume.as_deref() {
+            Some(volume) if volumes.contains(volume) && !covers.contains_key(volume) => volume,
+            _ => continue,
+        };
+
+        match download_cover(client, manga_id, data).await {
+            Ok(cover) => {
+                covers.insert(volume.to_owned(), cover);
+            }
+            Err(e) => error!("Failed to download cover of volume {volume} for {manga_id}: {e}"),
+        }
+    }
+
+    if let (Some(manga_name), Some(latest)) = (manga_name, cover_data.last()) {
+        let cached = latest
+            .attributes
+            .volume
+            .as_ref()
+            .and_then(|volume| covers.get(volume))
+            .cloned();
+        let cover = match cached {
+            Some(cover) => Ok(cover),
+            None => download_cover(client, manga_id, latest).await,
+        };
+
+        if let Err(e) = cover.and_then(|cover| save_series_cover(manga_name, &cover)) {
+            error!("Failed to save series cover for {manga_id}: {e}");
+        }
+    }
+
+    Ok(covers)
+}
+
+fn save_series_cover(manga_name: &str, cover: &Cover) -> Result<()> {
+    let data = match cover.extension.to_ascii_lowercase().as_str() {
+        "jpg" | "jpeg" => cover.data.to_vec(),
+        _ => processing::to_jpeg(&cover.data, COVER_QUALITY)?,
+    };
+
+    let series_path = get_series_path(manga_name)?;
+    fs::create_dir_all(&series_path)?;
+    fs::write(series_path.join("cover.jpg"), data)?;
+
+    Ok(())
+}
+
+async fn download_cover(
+    client: &reqwest::Client,
+    manga_id: &str,
+    cover: &CoverData,
+) -> Result<Cover> {
+    let response = client.get(cover.url(manga_id)).send().await?;
+    let data = http::check_status(response)?.bytes().await?;
+
+    Ok(Cover {
+        data,
+        extension: cover.extension().to_owned(),
+    })
+}
 
-    fs::create_dir_all(chapter_path)?;
+async fn download_chapter(
+    chapter: ChapterProps,
+    archive_path: PathBuf,
+    cover: Option<Cover>,
+    profile: OutputProfile,
+    device: Option<&DeviceProfile>,
+    limiter: &RequestLimiter,
+    token: DownloadToken,
+) -> Result<DownloadStatus> {
+    let at_home = get_at_home(&chapter.id).await?;
+    let source = ChapterSource::new(&chapter.id, at_home);
+    let total_frames = source.files.len();
+
+    // checked before the archive exists so no empty file is left behind
+    if total_frames == 0 {
+        return Ok(DownloadStatus::Unavailable);
+    }
 
-    let frame_urls = at_home.chapter.data_saver;
-    let total_frames = frame_urls.len();
-    let stream = stream::iter(frame_urls)
+    let client = http::client()?;
+
+    let epub = device
+        .filter(|device| device.archive != ArchiveFormat::Cbz)
+        .map(|device| {
+            let language = chapter.translated_language.as_deref();
+            EpubBook::new(&chapter.id, &chapter.fullname, language, device)
+        });
+    let mut archive = ArchiveWriter::create(archive_path, epub)?;
+    archive.set_comment(serde_json::to_string(&ReleaseInfo::new(
+        &chapter, &profile,
+    ))?);
+
+    // frames are processed and written on a blocking thread while the rest
+    // is still downloading
+    let pad_width = processing::page_pad_width(total_frames, &profile);
+    let (sender, receiver) = mpsc::channel();
+    let writer = tauri::async_runtime::spawn_blocking(move || {
+        write_archive(archive, receiver, cover, total_frames, profile)
+    });
+
+    let mut stream = stream::iter(source.files.iter())
         .enumerate()
         .map(|(index, file_name)| {
-            let frame_url = get_frame_url(&base_url, &hash, &file_name);
-
-            download_frame(
-                &chapter_id,
-                frame_url,
-                file_name,
-                &client,
-                chapter_path,
-                index,
-                total_frames,
-                MAX_FRAME_RETRIES,
-            )
-        })
-        .buffer_unordered(CONCURRENT_FRAMES);
+            let frame_name = get_frame_name(file_name, index + 1, pad_width);
 
-    stream
-        .for_each(|e| async {
-            match e {
-                Ok(name) => info!("Successfully downloaded {name}"),
-                Err(e) => error!("Failed to download {e}"),
-            };
+            download_frame(&source, &client, limiter, token, index)
+                .map(move |result| (index, frame_name, result))
         })
-        .await;
+        // every frame is queued at once, the limiter alone decides how many
+        // requests of all chapters run at the same time
+        .buffer_unordered(total_frames);
+
+    let mut failure = None;
+
+    while let Some((index, frame_name, result)) = stream.next().await {
+        let frame = match result {
+            Ok(data) => {
+                info!("Successfully downloaded {frame_name}");
+                Frame {
+                    name: frame_name,
+                    data,
+                }
+            }
+            Err(e) => {
+                error!("Failed to download {frame_name}: {e}");
+                // the writer discards the archive, the remaining frames are of no use
+                let _ = sender.send((index, None));
+                failure = Some(e);
+                break;
+            }
+        };
+
+        // the writer only hangs up after failing, which is reported below
+        if sender.send((index, Some(frame))).is_err() {
+            break;
+        }
+    }
 
-    write_zip(chapter_path, &chapter_name)?;
+    drop(stream);
+    drop(sender);
 
-    Ok(())
+    let written = writer
+        .await
+        .map_err(|e| ServiceError::Internal(e.to_string()))?;
+
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(DownloadStatus::Downloaded { path: written? }),
+    }
+}
+
+struct Frame {
+    name: String,
+    data: Bytes,
+}
+
+// writes frames in page order, holding back the ones that finished early, a
+// missing frame (`None`) discards the whole archive
+fn write_archive(
+    archive: ArchiveWriter,
+    frames: mpsc::Receiver<(usize, Option<Frame>)>,
+    cover: Option<Cover>,
+    total_frames: usize,
+    profile: OutputProfile,
+) -> Result<PathBuf> {
+    let tmp_path = archive.tmp_path().to_owned();
+    let result = fill_archive(archive, frames, cover, total_frames, profile);
+
+    if result.is_err() {
+        let _ = fs::remove_file(tmp_path);
+    }
+
+    result
+}
+
+fn fill_archive(
+    mut archive: ArchiveWriter,
+    frames: mpsc::Receiver<(usize, Option<Frame>)>,
+    cover: Option<Cover>,
+    total_frames: usize,
+    profile: OutputProfile,
+) -> Result<PathBuf> {
+    let pad_width = processing::page_pad_width(total_frames, &profile);
+
+    if let Some(cover) = cover {
+        // the cover is a single page even when spreads are split
+        let cover_profile = OutputProfile {
+            split_spreads: false,
+            ..profile.clone()
+        };
+        for page in processing::process_image(&cover.data, &cover.extension, &cover_profile)? {
+            let cover_name = processing::page_name(0, pad_width, &page.extension);
+            archive.add_page(&cover_name, &page.data)?;
+        }
+    }
+
+    let mut processor = ChapterProcessor::new(profile, pad_width);
+    let mut pending = BTreeMap::new();
+    let mut next_index = 0;
+
+    let mut write_frame = |archive: &mut ArchiveWriter, frame: Frame| -> Result<()> {
+        for page in processor.push(&frame.name, &frame.data)? {
+            archive.add_page(&page.name, &page.data)?;
+        }
+        Ok(())
+    };
+
+    for (index, frame) in frames {
+        let frame = frame.ok_or_else(|| {
+            ServiceError::Internal(format!("page {} failed to download", index + 1))
+        })?;
+        pending.insert(index, frame);
+
+        while let Some(frame) = pending.remove(&next_index) {
+            write_frame(&mut archive, frame)?;
+            next_index += 1;
+        }
+    }
+
+    if next_index < total_frames {
+        return Err(ServiceError::Internal(format!(
+            "only {next_index} of {total_frames} pages were downloaded"
+        )));
+    }
+
+    for page in processor.finish()? {
+        archive.add_page(&page.name, &page.data)?;
+    }
+
+    archive.finish()
 }
 
-fn get_chapter_path(chapter_name: &str) -> PathBuf {
-    let mut chapter_path_buf =
-        tauri::api::path::download_dir().expect("downloads path must be present");
-    chapter_path_buf.push(chapter_name);
-    chapter_path_buf.to_owned()
+fn get_downloads_path() -> Result<PathBuf> {
+    tauri::api::path::download_dir()
+        .ok_or_else(|| ServiceError::Internal("downloads folder is not available".to_owned()))
 }
 
-fn get_frame_name(file_name: &str, frame_index: usize, total_frames: usize) -> String {
+fn get_series_path(manga_name: &str) -> Result<PathBuf> {
+    Ok(get_downloads_path()?.join(sanitize_file_name(manga_name)))
+}
+
+fn get_archive_path(chapter: &ChapterProps, extension: &str) -> Result<PathBuf> {
+    let base_path = match &chapter.manga_name {
+        Some(manga_name) => get_series_path(manga_name)?,
+        None => get_downloads_path()?,
+    };
+    let chapter_name = sanitize_file_name(&chapter.fullname);
+
+    Ok(base_path.join(format!("{chapter_name}.{extension}")))
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    sanitized.trim().trim_end_matches('.').to_owned()
+}
+
+fn get_frame_name(file_name: &str, frame_index: usize, pad_width: usize) -> String {
     let ext = Path::new(file_name)
         .extension()
         .and_then(OsStr::to_str)
         .unwrap_or("jpg");
-    let pad_width = get_pad_width(total_frames);
-
-    format!("{:0width$}.{ext}", frame_index, width = pad_width)
-}
 
-fn get_pad_width(max_num: usize) -> usize {
-    max_num.to_string().len()
+    processing::page_name(frame_index, pad_width, ext)
 }
 
-async fn get_at_home(chapter_id: &str) -> Result<AtHomeResponse> {
+pub async fn get_at_home(chapter_id: &str) -> Result<AtHomeResponse> {
     let at_home_url = format!("{MANGADEX_API}/at-home/server/{chapter_id}");
 
-    let res: AtHomeResponse = reqwest::get(at_home_url).await?.json().await?;
+    let response = http::client()?.get(at_home_url).send().await?;
+    let res: AtHomeResponse = http::check_status(response)?.json().await?;
 
     Ok(res)
 }
 
-fn get_frame_url(base_url: &str, hash: &str, file_name: &str) -> String {
+pub fn get_frame_url(base_url: &str, hash: &str, file_name: &str) -> String {
     format!("{base_url}/data-saver/{hash}/{file_name}")
 }
 
-//FIXME: Fix too many args warning
-#[async_recursion]
-#[allow(clippy::too_many_arguments)]
+// each attempt fetches from the chapter's current server, a failure swaps the
+// server out unless another frame already did so since the attempt started
 async fn download_frame(
-    chapter_id: &str,
-    frame_url: String,
-    file_name: String,
+    source: &ChapterSource,
     client: &reqwest::Client,
-    chapter_path: &Path,
+    limiter: &RequestLimiter,
+    token: DownloadToken,
     frame_index: usize,
-    total_frames: usize,
-    retries_left: u32,
-) -> Result<String> {
-    let frame_name = get_frame_name(&file_name, frame_index, total_frames);
-    let file_path = chapter_path.join(&frame_name);
-    let start = Instant::now();
-    let response = client.get(frame_url).send().await?;
-    let end = Instant::now();
-    let duration = end.duration_since(start).as_millis();
-
-    let download_successful = report_frame(client, &response, duration).await?;
-    let has_retries_left = retries_left - 1 > 0;
-    if !download_successful && has_retries_left {
-        let at_home = get_at_home(chapter_id).await?;
-        let frame_urls = &at_home.chapter.data_saver;
-        let file_name = &frame_urls[frame_index];
-        let frame_url = get_frame_url(&at_home.base_url, &at_home.chapter.hash, file_name);
-
-        return download_frame(
-            chapter_id,
-            frame_url,
-            file_name.clone(),
-            client,
-            chapter_path,
-            frame_index,
-            total_frames,
-            retries_left - 1,
-        )
-        .await;
+) -> Result<Bytes> {
+    let file_name = &source.files[frame_index];
+    let mut failures = Vec::new();
+
+    for attempt in 1..=MAX_FRAME_RETRIES {
+        // a window may close while a long chapter is still downloading
+        limiter.wait_for_window(token).await?;
+
+        let server = source.server().await;
+        let frame_url = get_frame_url(&server.base_url, &source.hash, file_name);
+
+        let permit = limiter.acquire(&frame_url).await;
+        let result = get_frame(client, &frame_url, limiter).await;
+        drop(permit);
+
+        match result {
+            Ok(data) => return Ok(data),
+            Err(e) => {
+                let reason = failure_reason(&e);
+                warn!(
+                    "attempt {attempt} for {file_name} of {} failed on {}: {reason}",
+                    source.chapter_id, server.base_url
+                );
+                failures.push(format!("{}: {reason}", server.base_url));
+
+                source.rotate(server.generation).await;
+                if attempt < MAX_FRAME_RETRIES {
+                    tokio::time::sleep(retry_delay(attempt)).await;
+                }
+            }
+        }
     }
 
-    let file_data = response.bytes().await?;
+    Err(ServiceError::Internal(format!(
+        "failed to download {file_name} after {MAX_FRAME_RETRIES} attempts ({})",
+        failures.join("; ")
+    )))
+}
+
+// doubles with every failed attempt so a struggling server gets some room
+fn retry_delay(attempt: u32) -> Duration {
+    FRAME_RETRY_DELAY
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(MAX_FRAME_RETRY_DELAY)
+}
 
-    fs::write(file_path, file_data)?;
+fn failure_reason(e: &ServiceError) -> String {
+    match e {
+        ServiceError::HttpError(e) => match e.status() {
+            Some(status) => format!("status {status}"),
+            None if e.is_timeout() => "timed out".to_owned(),
+            None if e.is_connect() => "connection failed".to_owned(),
+            None => e.to_string(),
+        },
+        e => e.to_string(),
+    }
+}
 
-    Ok(file_name)
+/// Server assignment of a chapter, fetched once and shared by its frames.
+struct ChapterSource {
+    chapter_id: String,
+    hash: String,
+    files: Vec<String>,
+    server: tokio::sync::Mutex<Server>,
 }
 
-#[derive(Serialize)]
-struct FrameReport<'a> {
-    url: &'a Url,
-    success: bool,
-    cached: bool,
-    bytes: u64,
-    duration: u128,
+#[derive(Clone)]
+struct Server {
+    base_url: String,
+    generation: u32,
 }
 
-async fn report_frame(
-    client: &reqwest::Client,
-    response: &Response,
-    duration: u128,
-) -> Result<bool> {
-    let url = response.url();
-    let status = response.status();
-    let success = status.is_success();
-
-    let has_mangadex_domain = match url.host_str() {
-        Some(host) => host.contains("mangadex.org"),
-        None => false,
-    };
+impl ChapterSource {
+    fn new(chapter_id: &str, at_home: AtHomeResponse) -> Self {
+        ChapterSource {
+            chapter_id: chapter_id.to_owned(),
+            hash: at_home.chapter.hash,
+            files: at_home.chapter.data_saver,
+            server: tokio::sync::Mutex::new(Server {
+                base_url: at_home.base_url,
+                generation: 0,
+            }),
+        }
+    }
 
-    if has_mangadex_domain {
-        return Ok(success);
+    async fn server(&self) -> Server {
+        self.server.lock().await.clone()
     }
 
-    let bytes = response.content_length().unwrap_or(0);
-    let cache = response.headers().get("X-Cache");
-    let cached = match cache {
-        Some(cache) => cache.to_str().unwrap_or("").contains("HIT"),
-        None => false,
-    };
+    async fn rotate(&self, failed_generation: u32) {
+        let new_server = async {
+            get_at_home(&self.chapter_id)
+                .await
+                .map(|at_home| at_home.base_url)
+        };
 
-    client
-        .post(MANGADEX_REPORT_URL)
-        .json(&FrameReport {
-            url,
-            success,
-            bytes,
-            cached,
-            duration,
-        })
-        .send()
-        .await?;
+        self.rotate_to(failed_generation, new_server).await;
+    }
+
+    // the lock is held while asking for a new server so frames failing at
+    // the same time wait for it instead of each requesting their own, the
+    // request is only awaited when the failed server is still in use
+    async fn rotate_to(
+        &self,
+        failed_generation: u32,
+        new_server: impl Future<Output = Result<String>>,
+    ) {
+        let mut server = self.server.lock().await;
+        if server.generation != failed_generation || server.base_url == MANGADEX_UPLOADS {
+            return;
+        }
 
-    Ok(success)
+        let base_url = if server.generation < MAX_SERVER_ROTATIONS {
+            match new_server.await {
+                Ok(base_url) if base_url != server.base_url => Some(base_url),
+                Ok(_) => None,
+                Err(e) => {
+                    warn!("failed to get a new server for {}: {e}", self.chapter_id);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        server.base_url = base_url.unwrap_or_else(|| {
+            info!("falling back to {MANGADEX_UPLOADS} for {}", self.chapter_id);
+            MANGADEX_UPLOADS.to_owned()
+        });
+        server.generation += 1;
+    }
 }
 
-fn write_zip(chapter_path: &Path, chapter_name: &str) -> Result<()> {
-    let zip_path = chapter_path.with_file_name(format!("{chapter_name}.cbz"));
-    let zip_file = fs::File::create(zip_path)?;
+// the online reader is interactive, so it shares the bandwidth cap but not the
+// download windows
+pub async fn fetch_frame(
+    client: &reqwest::Client,
+    frame_url: &str,
+    limiter: &RequestLimiter,
+) -> Result<Bytes> {
+    get_frame(client, frame_url, limiter).await
+}
 
-    let mut writer = zip::ZipWriter::new(zip_file);
+// the report is queued once the body is in, so it carries the real size and
+// transfer time without holding up the download
+async fn get_frame(
+    client: &reqwest::Client,
+    frame_url: &str,
+    limiter: &RequestLimiter,
+) -> Result<Bytes> {
+    let start = Instant::now();
+    let mut cached = false;
 
-    let method = zip::CompressionMethod::Stored;
-    let options = FileOptions::default()
-        .compression_method(method)
-        .unix_permissions(0o755);
+    let result = async {
+        let response = http::check_status(client.get(frame_url).send().await?)?;
+        cached = reports::is_cache_hit(&response);
 
-    let mut buffer = Vec::new();
+        limiter.read_body(response).await
+    }
+    .await;
 
-    for entry in fs::read_dir(chapter_path)? {
-        let entry = entry?;
-        let path = entry.path();
-        if !path.is_file() {
-            continue;
-        }
+    let (success, bytes) = match &result {
+        Ok(data) => (true, data.len()),
+        Err(_) => (false, 0),
+    };
+    reports::report(FrameReport::new(
+        frame_url,
+        success,
+        cached,
+        bytes,
+        start.elapsed(),
+    ));
+
+    result
+}
 
-        let file_name = path.strip_prefix(chapter_path).unwrap().to_str().unwrap();
-        writer.start_file(file_name, options)?;
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reader::natural_cmp;
+
+    #[test]
+    fn frame_names_sort_in_page_order() {
+        let total_frames = 120;
+        let pad_width = processing::page_pad_width(total_frames, &OutputProfile::default());
+        let names: Vec<String> = (0..=total_frames)
+            .map(|index| get_frame_name("x1.png", index, pad_width))
+            .collect();
+
+        let mut lexicographic = names.clone();
+        lexicographic.sort();
+        let mut natural = names.clone();
+        natural.sort_by(|a, b| natural_cmp(a, b));
+
+        assert_eq!(names[0], "000.png");
+        assert_eq!(names[120], "120.png");
+        assert_eq!(lexicographic, names);
+        assert_eq!(natural, names);
+    }
 
-        let mut f = fs::File::open(path)?;
-        f.read_to_end(&mut buffer)?;
-        writer.write_all(&*buffer)?;
-        buffer.clear()
+    #[test]
+    fn matches_series_by_alternate_titles() {
+        let manga: MangaData = serde_json::from_value(serde_json::json!({
+            "id": "frieren",
+            "attributes": {
+                "title": { "ja-ro": "Sousou no Frieren" },
+                "altTitles": [
+                    { "en": "Frieren: Beyond Journey's End" },
+                    { "fr": "Frieren" },
+                ],
+                "originalLanguage": "ja",
+            },
+        }))
+        .unwrap();
+        let locales = Locales::new(vec!["fr".to_string()]);
+
+        assert!(is_series_title(&manga, "sousou no frieren", &locales));
+        assert!(is_series_title(&manga, "Frieren", &locales));
+        assert!(is_series_title(
+            &manga,
+            "Frieren: Beyond Journey's End",
+            &locales
+        ));
+        assert!(!is_series_title(&manga, "Frieren 2", &locales));
     }
 
-    writer.finish()?;
+    #[test]
+    fn encodes_search_queries() {
+        let url = get_search_url("Tom & Jerry #1").unwrap();
 
-    fs::remove_dir_all(chapter_path)?;
+        assert!(url.ends_with("limit=5&title=Tom+%26+Jerry+%231"));
+    }
 
-    Ok(())
+    fn source(base_url: &str) -> ChapterSource {
+        ChapterSource {
+            chapter_id: "chapter".to_owned(),
+            hash: "hash".to_owned(),
+            files: vec!["1.png".to_owned()],
+            server: tokio::sync::Mutex::new(Server {
+                base_url: base_url.to_owned(),
+                generation: 0,
+            }),
+        }
+    }
+
+    #[test]
+    fn rotates_servers_once_per_failure() {
+        futures::executor::block_on(async {
+            let source = source("https://a.example");
+
+            source
+                .rotate_to(0, async { Ok("https://b.example".to_owned()) })
+                .await;
+            // a frame that failed on the old server must not rotate again
+            source
+                .rotate_to(0, async { Ok("https://c.example".to_owned()) })
+                .await;
+
+            let server = source.server().await;
+            assert_eq!(server.base_url, "https://b.example");
+            assert_eq!(server.generation, 1);
+        });
+    }
+
+    #[test]
+    fn falls_back_to_uploads() {
+        futures::executor::block_on(async {
+            let same = source("https://a.example");
+            same.rotate_to(0, async { Ok("https://a.example".to_owned()) })
+                .await;
+            assert_eq!(same.server().await.base_url, MANGADEX_UPLOADS);
+
+            let failing = source("https://a.example");
+            failing
+                .rotate_to(0, async { Err(ServiceError::Internal("down".to_owned())) })
+                .await;
+            assert_eq!(failing.server().await.base_url, MANGADEX_UPLOADS);
+
+            let exhausted = source("https://a.example");
+            for generation in 0..MAX_SERVER_ROTATIONS {
+                let base_url = format!("https://{generation}.example");
+                exhausted
+                    .rotate_to(generation, async { Ok(base_url) })
+                    .await;
+            }
+            assert_eq!(
+                exhausted.server().await.base_url,
+                format!("https://{}.example", MAX_SERVER_ROTATIONS - 1)
+            );
+
+            // past the last rotation only the uploads server is left
+            exhausted
+                .rotate_to(MAX_SERVER_ROTATIONS, async {
+                    Ok("https://z.example".to_owned())
+                })
+                .await;
+            let server = exhausted.server().await;
+            assert_eq!(server.base_url, MANGADEX_UPLOADS);
+            assert_eq!(server.generation, MAX_SERVER_ROTATIONS + 1);
+        });
+    }
+
+    #[test]
+    fn backs_off_between_retries() {
+        assert_eq!(retry_delay(1), FRAME_RETRY_DELAY);
+        assert_eq!(retry_delay(2), FRAME_RETRY_DELAY * 2);
+        assert_eq!(retry_delay(3), FRAME_RETRY_DELAY * 4);
+        assert_eq!(retry_delay(MAX_FRAME_RETRIES), MAX_FRAME_RETRY_DELAY);
+    }
 }
@@ -0,0 +1,267 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::collision::CollisionPolicy;
+use crate::constants::APP_DIR_NAME;
+use crate::devices::find_device_profile;
+use crate::http::NetworkSettings;
+use crate::limits::{ConcurrencySettings, ScheduleSettings};
+use crate::model::{Result, ServiceError};
+use crate::processing::OutputProfile;
+use crate::store::JsonStore;
+
+pub const SETTINGS_CHANGED_EVENT: &str = "settings-changed";
+
+const SETTINGS_FILE: &str = "settings.json";
+const SETTINGS_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub version: u32,
+    /// Preferred locales for titles and descriptions, most wanted first.
+    pub locales: Vec<String>,
+    /// Translation language of listed and downloaded chapters.
+    pub chapter_language: String,
+    pub output: OutputProfile,
+    /// Device profile id, overrides `output` when set.
+    pub device: Option<String>,
+    pub collision: CollisionPolicy,
+    pub concurrency: ConcurrencySettings,
+    pub schedule: ScheduleSettings,
+    pub network: NetworkSettings,
+    /// Folders scanned for chapters downloaded by other tools.
+    pub library_folders: Vec<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            version: SETTINGS_VERSION,
+            locales: vec!["en".to_owned()],
+            chapter_language: "en".to_owned(),
+            output: OutputProfile::default(),
+            device: None,
+            collision: CollisionPolicy::default(),
+            concurrency: ConcurrencySettings::default(),
+            schedule: ScheduleSettings::default(),
+            network: NetworkSettings::default(),
+            library_folders: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |message: &str| Err(ServiceError::InvalidArguments(message.to_owned()));
+
+        if self.version != SETTINGS_VERSION {
+            return invalid("unsupported settings version");
+        }
+        if self.locales.iter().all(|locale| locale.trim().is_empty()) {
+            return invalid("at least one locale is required");
+        }
+        if self.chapter_language.trim().is_empty() {
+            return invalid("chapter language is required");
+        }
+        if !(1..=100).contains(&self.output.quality) {
+            return invalid("quality must be between 1 and 100");
+        }
+        if !(-100.0..=100.0).contains(&self.output.contrast) {
+            return invalid("contrast must be between -100 and 100");
+        }
+        if [
+            self.output.max_width,
+            self.output.max_height,
+            self.output.strip_height,
+        ]
+        .contains(&Some(0))
+        {
+            return invalid("page sizes must be positive");
+        }
+
+        let concurrency = &self.concurrency;
+        if concurrency.chapters == 0 || concurrency.frame_requests == 0 || concurrency.per_host == 0
+        {
+            return invalid("concurrency limits must be positive");
+        }
+        if self.schedule.max_bytes_per_sec == Some(0) {
+            return invalid("bandwidth limit must be positive");
+        }
+        self.network.validate()?;
+
+        if let Some(device) = &self.device {
+            find_device_profile(device)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct SettingsStore {
+    store: JsonStore<Settings>,
+}
+
+impl Default for SettingsStore {
+    fn default() -> Self {
+        SettingsStore::load(get_settings_path())
+    }
+}
+
+impl SettingsStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let store = JsonStore::load_with("settings", path, |path| {
+            read_settings(path).map_err(|e| {
+                back_up(path);
+                e
+            })
+        });
+
+        SettingsStore {
+            store: store.pretty(),
+        }
+    }
+
+    pub fn get(&self) -> Settings {
+        self.store.lock().clone()
+    }
+
+    pub fn update(&self, settings: Settings) -> Result<Settings> {
+        settings.validate()?;
+
+        let mut current = self.store.lock();
+        self.store.save(&settings)?;
+        *current = settings;
+
+        Ok(current.clone())
+    }
+}
+
+fn read_settings(path: &Path) -> Result<Settings> {
+    let value = migrate(serde_json::from_slice(&fs::read(path)?)?)?;
+
+    match serde_json::from_value::<Settings>(value.clone()) {
+        Ok(settings) if settings.validate().is_ok() => Ok(settings),
+        _ => {
+            back_up(path);
+            Ok(recover(value))
+        }
+    }
+}
+
+// takes over every field that is valid on its own, so one bad value doesn't
+// reset everything else to the defaults
+fn recover(value: Value) -> Settings {
+    let mut settings = Settings::default();
+
+    if let Value::Object(fields) = value {
+        for (key, field) in fields {
+            let mut candidate = serde_json::to_value(&settings).unwrap_or_default();
+            candidate[key.as_str()] = field;
+
+            match serde_json::from_value::<Settings>(candidate) {
+                Ok(candidate) if candidate.validate().is_ok() => settings = candidate,
+                _ => warn!("invalid setting \"{key}\", using the default"),
+            }
+        }
+    }
+
+    settings
+}
+
+// the next update overwrites the file, the copy keeps what the user had
+fn back_up(path: &Path) {
+    let backup_path = path.with_extension("json.bak");
+
+    match fs::copy(path, &backup_path) {
+        Ok(_) => warn!("kept a copy of the settings in {}", backup_path.display()),
+        Err(e) => error!(
+            "failed to back up settings to {}: {e}",
+            backup_path.display()
+        ),
+    }
+}
+
+// upgrades older files one version at a time, each step only knows its
+// neighbour so new versions just add an arm
+fn migrate(mut value: Value) -> Result<Value> {
+    if !value.is_object() {
+        return Err(ServiceError::InvalidArguments(
+            "settings must be a JSON object".to_owned(),
+        ));
+    }
+
+    loop {
+        let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+
+        match version {
+            // files written before versioning had the same fields
+            0 => value["version"] = Value::from(1),
+            v if v == u64::from(SETTINGS_VERSION) => return Ok(value),
+            v => {
+                return Err(ServiceError::InvalidArguments(format!(
+                    "settings version {v} is newer than this app"
+                )))
+            }
+        }
+    }
+}
+
+fn get_settings_path() -> Option<PathBuf> {
+    tauri::api::path::config_dir().map(|dir| dir.join(APP_DIR_NAME).join(SETTINGS_FILE))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn migrates_unversioned_files() {
+        let migrated = migrate(json!({ "chapterLanguage": "fr" })).unwrap();
+        let settings: Settings = serde_json::from_value(migrated).unwrap();
+
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.chapter_language, "fr");
+        assert_eq!(settings.locales, ["en"]);
+        assert!(migrate(json!({ "version": 99 })).is_err());
+        assert!(migrate(json!([1, 2])).is_err());
+        assert!(migrate(json!(null)).is_err());
+    }
+
+    #[test]
+    fn keeps_valid_fields_of_invalid_files() {
+        let settings = recover(json!({
+            "version": SETTINGS_VERSION,
+            "chapterLanguage": "fr",
+            "libraryFolders": ["/comics"],
+            "concurrency": { "chapters": 0 },
+            "collision": "unknown",
+        }));
+
+        assert_eq!(settings.chapter_language, "fr");
+        assert_eq!(settings.library_folders, [PathBuf::from("/comics")]);
+        assert_eq!(settings.concurrency, ConcurrencySettings::default());
+        assert_eq!(settings.collision, CollisionPolicy::default());
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        assert!(Settings::default().validate().is_ok());
+
+        let mut settings = Settings::default();
+        settings.concurrency.per_host = 0;
+        assert!(settings.validate().is_err());
+
+        let settings = Settings {
+            device: Some("unknown".to_string()),
+            ..Default::default()
+        };
+        assert!(settings.validate().is_err());
+    }
+}
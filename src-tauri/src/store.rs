@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use log::{error, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::model::Result;
+
+/// A value kept in memory and persisted as a JSON file in the app directories.
+pub struct JsonStore<T> {
+    name: &'static str,
+    path: Option<PathBuf>,
+    pretty: bool,
+    data: Mutex<T>,
+}
+
+impl<T: Default + Serialize + DeserializeOwned> JsonStore<T> {
+    /// Reads the file at `path`, falls back to the default when it's missing or unreadable.
+    pub fn load(name: &'static str, path: Option<PathBuf>) -> Self {
+        JsonStore::load_with(name, path, |path| {
+            Ok(serde_json::from_slice(&fs::read(path)?)?)
+        })
+    }
+
+    /// Like `load`, with a custom reader for files that need migrating or recovering.
+    pub fn load_with(
+        name: &'static str,
+        path: Option<PathBuf>,
+        read: impl FnOnce(&Path) -> Result<T>,
+    ) -> Self {
+        let data = path
+            .as_ref()
+            .filter(|path| path.exists())
+            .and_then(|path| match read(path) {
+                Ok(data) => Some(data),
+                Err(e) => {
+                    error!("failed to load {name} from {}: {e}", path.display());
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        JsonStore {
+            name,
+            path,
+            pretty: false,
+            data: Mutex::new(data),
+        }
+    }
+
+    /// Writes indented JSON, for files users may edit by hand.
+    pub fn pretty(mut self) -> Self {
+        self.pretty = true;
+        self
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Writes `data` to a temporary file first, so a crash never leaves half a file behind.
+    pub fn save(&self, data: &T) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => {
+                warn!("no app directory, {} is not persisted", self.name);
+                return Ok(());
+            }
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let json = if self.pretty {
+            serde_json::to_vec_pretty(data)?
+        } else {
+            serde_json::to_vec(data)?
+        };
+
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, json)?;
+        fs::rename(tmp_path, path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn persists_and_falls_back_to_default() {
+        let dir = std::env::temp_dir().join(format!("store-{}", std::process::id()));
+        let path = dir.join("data.json");
+        let store: JsonStore<HashMap<String, u32>> = JsonStore::load("data", Some(path.clone()));
+
+        let mut data = store.lock();
+        data.insert("a".to_string(), 1);
+        store.save(&data).unwrap();
+        drop(data);
+
+        let reloaded = JsonStore::<HashMap<String, u32>>::load("data", Some(path.clone()));
+        assert_eq!(reloaded.lock().get("a"), Some(&1));
+        assert!(!path.with_extension("json.tmp").exists());
+
+        fs::write(&path, "{ not json").unwrap();
+        let broken = JsonStore::<HashMap<String, u32>>::load("data", Some(path));
+        fs::remove_dir_all(dir).unwrap();
+
+        assert!(broken.lock().is_empty());
+        assert!(JsonStore::<HashMap<String, u32>>::load("data", None)
+            .save(&HashMap::new())
+            .is_ok());
+    }
+}
@@ -1,8 +1,39 @@
 import { invoke } from "@tauri-apps/api";
 import { get } from "svelte/store";
 import { debug, error } from 'tauri-plugin-log-api';
-import { selectedChapters } from "./store";
+import { locales, selectedChapters } from "./store";
 
+export type ErrorCode =
+  | 'invalidArguments'
+  | 'network'
+  | 'networkSettings'
+  | 'rateLimited'
+  | 'notFound'
+  | 'api'
+  | 'unauthorized'
+  | 'diskFull'
+  | 'permissionDenied'
+  | 'cancelled'
+  | 'internal';
+
+export type ResponseError = {
+  id: string,
+  status: number,
+  title: string,
+  detail: string,
+}
+
+export type CommandError = {
+  code: ErrorCode,
+  message: string,
+  retryable: boolean,
+  retryAfter?: number,
+  errors?: ResponseError[],
+}
+
+export function isCommandError(e: unknown): e is CommandError {
+  return typeof e === 'object' && e !== null && 'code' in e && 'retryable' in e;
+}
 
 export type MangaView = {
   id: string
@@ -15,7 +46,7 @@ export type MangaView = {
 
 export async function search(query: string) {
   try {
-    const views: MangaView[] = await invoke('search', { query });
+    const views: MangaView[] = await invoke('search', { query, locales: get(locales) });
     debug(`received search results: ${JSON.stringify(views, null, 2)}`);
     return views;
   } catch (e) {
@@ -24,16 +55,34 @@ export async function search(query: string) {
   }
 }
 
+export type MangaLinks = {
+  anilist?: string,
+  myAnimeList?: string,
+  officialRaw?: string,
+  officialEnglish?: string,
+}
+
 export type Manga = {
   view: MangaView,
+  altTitles: string[],
   year?: number,
-  avg_score?: number,
+  avgScore?: number,
+  follows?: number,
   author?: string,
+  artist?: string,
+  themes: string[],
+  formats: string[],
+  longStrip: boolean,
+  originalLanguage?: string,
+  demographic?: string,
+  lastVolume?: string,
+  lastChapter?: string,
+  links: MangaLinks,
 }
 
 export async function getManga(id: string) {
   try {
-    const manga: Manga = await invoke('get_manga', { id });
+    const manga: Manga = await invoke('get_manga', { id, locales: get(locales) });
     debug(`received manga: ${JSON.stringify(manga, null, 2)}`);
     return manga;
   } catch (e) {
@@ -91,14 +140,18 @@ export class ChapterPage {
 
 export type Chapter = {
   id: string
-  chapter: string
+  chapter?: string
   volume?: string
   title?: string
   scanGroup?: ScanGroup
   pages: number
   externalUrl?: string
+  availability: ChapterAvailability
+  translatedLanguage?: string
 }
 
+export type ChapterAvailability = 'available' | 'external' | 'unavailable';
+
 export type Volume = {
   volume: string
   chapters: Chapter[]
@@ -123,16 +176,187 @@ export async function getChapters(props: GetChapterProps) {
   }
 }
 
-export async function downloadChapters() {
+export type OutputFormat = 'original' | 'jpeg' | 'png' | 'webp' | 'avif';
+
+export type OutputProfile = {
+  format: OutputFormat,
+  quality: number,
+  maxWidth?: number,
+  maxHeight?: number,
+  grayscale: boolean,
+  contrast: number,
+  trimBorders: boolean,
+  splitSpreads: boolean,
+  rightToLeft: boolean,
+  stripHeight?: number,
+}
+
+export type ArchiveFormat = 'cbz' | 'epub' | 'kepub';
+
+export type DeviceProfile = {
+  id: string,
+  name: string,
+  width: number,
+  height: number,
+  grayscale: boolean,
+  contrast: number,
+  archive: ArchiveFormat,
+  rightToLeft: boolean,
+}
+
+export async function getDeviceProfiles() {
+  try {
+    return await invoke<DeviceProfile[]>('get_device_profiles');
+  } catch (e) {
+    error(`failed to invoke command "get_device_profiles": ${JSON.stringify(e, null, 2)}`);
+    return [];
+  }
+}
+
+export type LibraryEntry = {
+  mangaId: string,
+  chapterId: string,
+  path: string,
+  addedAt: number,
+}
+
+export type ScanReport = {
+  imported: number,
+  known: number,
+  removed: number,
+  unmatched: string[],
+}
+
+export async function scanLibrary(folders?: string[], lang?: string) {
+  try {
+    return await invoke<ScanReport>('scan_library', { folders, lang });
+  } catch (e) {
+    error(`failed to invoke command "scan_library": ${JSON.stringify(e, null, 2)}`);
+    return null;
+  }
+}
+
+export async function getLibrary(mangaId: string) {
+  try {
+    return await invoke<LibraryEntry[]>('get_library', { mangaId });
+  } catch (e) {
+    error(`failed to invoke command "get_library": ${JSON.stringify(e, null, 2)}`);
+    return [];
+  }
+}
+
+export type ConcurrencySettings = {
+  chapters: number,
+  frameRequests: number,
+  perHost: number,
+}
+
+export type DownloadWindow = {
+  start: string,
+  end: string,
+}
+
+export type ScheduleSettings = {
+  maxBytesPerSec?: number,
+  windows: DownloadWindow[],
+}
+
+export type ProxySettings = {
+  url: string,
+  username?: string,
+  noProxy: string[],
+}
+
+export type NetworkSettings = {
+  proxy?: ProxySettings,
+  caBundle?: string,
+  userAgent?: string,
+  contact?: string,
+  reportFrames: boolean,
+}
+
+export type Settings = {
+  version: number,
+  locales: string[],
+  chapterLanguage: string,
+  output: OutputProfile,
+  device?: string,
+  collision: CollisionPolicy,
+  concurrency: ConcurrencySettings,
+  schedule: ScheduleSettings,
+  network: NetworkSettings,
+  libraryFolders: string[],
+}
+
+export const SETTINGS_CHANGED_EVENT = 'settings-changed';
+
+export async function getSettings() {
+  try {
+    return await invoke<Settings>('get_settings');
+  } catch (e) {
+    error(`failed to invoke command "get_settings": ${JSON.stringify(e, null, 2)}`);
+    return null;
+  }
+}
+
+export async function updateSettings(settings: Settings) {
+  try {
+    return await invoke<Settings>('update_settings', { settings });
+  } catch (e) {
+    error(`failed to invoke command "update_settings": ${JSON.stringify(e, null, 2)}`);
+    return null;
+  }
+}
+
+export async function setProxyPassword(password?: string) {
+  try {
+    await invoke('set_proxy_password', { password });
+  } catch (e) {
+    error(`failed to invoke command "set_proxy_password": ${JSON.stringify(e, null, 2)}`);
+  }
+}
+
+export type CollisionPolicy = 'skip' | 'overwrite' | 'rename' | 'replaceOtherRelease';
+
+export type DownloadStatus =
+  | { status: 'downloaded', path: string }
+  | { status: 'skipped' }
+  | { status: 'external', url: string }
+  | { status: 'unavailable' }
+  | { status: 'failed', error: CommandError };
+
+export type ChapterDownload = { chapterId: string } & DownloadStatus;
+
+export type DownloadReport = {
+  chapters: ChapterDownload[],
+}
+
+export type DownloadOptions = {
+  profile?: Partial<OutputProfile>,
+  device?: string,
+  collision?: CollisionPolicy,
+  stitchStrips?: boolean,
+}
+
+export async function downloadChapters(options?: DownloadOptions) {
   try {
     const chapters = get(selectedChapters).map(ch => ch.asObject());
-    await invoke('download', { chapters });
-    debug(`file downloaded`);
+    const report = await invoke<DownloadReport>('download', { chapters, options });
+    debug(`download finished: ${JSON.stringify(report, null, 2)}`);
+    return report;
   } catch (e) {
     error(`failed to invoke command "download": ${JSON.stringify(e, null, 2)}`);
+    return null;
   }
 }
 
+export async function cancelDownloads() {
+  try {
+    await invoke('cancel_downloads');
+  } catch (e) {
+    error(`failed to invoke command "cancelDownloads": ${JSON.stringify(e, null, 2)}`);
+  }
+}
 
 export class AggregatedChapters {
   constructor(
@@ -162,9 +386,10 @@ type VolumeAggregate = {
   chapters: { [key: string]: ChapterAggregate },
 }
 
-type ChapterAggregate = {
+export type ChapterAggregate = {
   chapter: string,
   id: string,
+  others: string[],
 }
 
 export async function aggregate(id: string, lang: string) {
@@ -174,4 +399,209 @@ export async function aggregate(id: string, lang: string) {
   } catch (e) {
     error(`failed to invoke command "aggregate": ${JSON.stringify(e, null, 2)}`);
   }
-}
\ No newline at end of file
+}
+
+export async function clearCache() {
+  try {
+    await invoke('clear_cache');
+  } catch (e) {
+    error(`failed to invoke command "clearCache": ${JSON.stringify(e, null, 2)}`);
+  }
+}
+
+export type PageView = {
+  index: number,
+  name: string,
+  url: string,
+}
+
+export type ArchiveView = {
+  id: string,
+  title: string,
+  path: string,
+  pages: PageView[],
+}
+
+export async function openArchive(path: string) {
+  try {
+    return await invoke<ArchiveView>('open_archive', { path });
+  } catch (e) {
+    error(`failed to invoke command "openArchive": ${JSON.stringify(e, null, 2)}`);
+  }
+}
+
+export async function getArchivePages(id: string) {
+  try {
+    return await invoke<PageView[]>('get_archive_pages', { id });
+  } catch (e) {
+    error(`failed to invoke command "getArchivePages": ${JSON.stringify(e, null, 2)}`);
+    return [];
+  }
+}
+
+export async function prefetchPages(id: string, from: number, count: number) {
+  try {
+    await invoke('prefetch_pages', { id, from, count });
+  } catch (e) {
+    error(`failed to invoke command "prefetchPages": ${JSON.stringify(e, null, 2)}`);
+  }
+}
+
+export async function closeArchive(id: string) {
+  try {
+    await invoke('close_archive', { id });
+  } catch (e) {
+    error(`failed to invoke command "closeArchive": ${JSON.stringify(e, null, 2)}`);
+  }
+}
+
+export async function openOnlineChapter(chapterId: string) {
+  try {
+    return await invoke<PageView[]>('open_online_chapter', { chapterId });
+  } catch (e) {
+    error(`failed to invoke command "openOnlineChapter": ${JSON.stringify(e, null, 2)}`);
+    return [];
+  }
+}
+
+export async function loadOnlinePage(chapterId: string, index: number) {
+  try {
+    await invoke('load_online_page', { chapterId, index });
+    return true;
+  } catch (e) {
+    error(`failed to invoke command "loadOnlinePage": ${JSON.stringify(e, null, 2)}`);
+    return false;
+  }
+}
+
+export async function closeOnlineChapter(chapterId: string) {
+  try {
+    await invoke('close_online_chapter', { chapterId });
+  } catch (e) {
+    error(`failed to invoke command "closeOnlineChapter": ${JSON.stringify(e, null, 2)}`);
+  }
+}
+
+export type ChapterProgress = {
+  read: boolean,
+  lastPage?: number,
+  updatedAt: number,
+}
+
+export async function getReadProgress(mangaId: string) {
+  try {
+    return await invoke<{ [chapterId: string]: ChapterProgress }>('get_read_progress', { mangaId });
+  } catch (e) {
+    error(`failed to invoke command "getReadProgress": ${JSON.stringify(e, null, 2)}`);
+    return {};
+  }
+}
+
+export async function markChapters(mangaId: string, chapterIds: string[], read: boolean) {
+  try {
+    await invoke('mark_chapters', { mangaId, chapterIds, read });
+  } catch (e) {
+    error(`failed to invoke command "markChapters": ${JSON.stringify(e, null, 2)}`);
+  }
+}
+
+export async function setLastPage(mangaId: string, chapterId: string, page: number) {
+  try {
+    await invoke('set_last_page', { mangaId, chapterId, page });
+  } catch (e) {
+    error(`failed to invoke command "setLastPage": ${JSON.stringify(e, null, 2)}`);
+  }
+}
+
+export async function nextUnreadChapter(mangaId: string, lang: string) {
+  try {
+    return await invoke<ChapterAggregate | null>('next_unread_chapter', { mangaId, lang });
+  } catch (e) {
+    error(`failed to invoke command "nextUnreadChapter": ${JSON.stringify(e, null, 2)}`);
+    return null;
+  }
+}
+
+export async function unreadChapters(mangaId: string, lang: string) {
+  try {
+    return await invoke<ChapterAggregate[]>('unread_chapters', { mangaId, lang });
+  } catch (e) {
+    error(`failed to invoke command "unreadChapters": ${JSON.stringify(e, null, 2)}`);
+    return [];
+  }
+}
+
+export type AuthStatus = {
+  loggedIn: boolean,
+  username?: string,
+}
+
+export type LoginProps = {
+  username: string,
+  password: string,
+  clientId: string,
+  clientSecret: string,
+}
+
+export type FollowedManga = {
+  view: MangaView,
+  readingStatus?: string,
+}
+
+export type ReadMarkersSync = {
+  pulled: number,
+  pushed: number,
+}
+
+export async function login(props: LoginProps) {
+  try {
+    return await invoke<AuthStatus>('login', props);
+  } catch (e) {
+    error(`failed to invoke command "login": ${JSON.stringify(e, null, 2)}`);
+    return undefined;
+  }
+}
+
+export async function logout() {
+  try {
+    await invoke('logout');
+  } catch (e) {
+    error(`failed to invoke command "logout": ${JSON.stringify(e, null, 2)}`);
+  }
+}
+
+export async function authStatus() {
+  try {
+    return await invoke<AuthStatus>('auth_status');
+  } catch (e) {
+    error(`failed to invoke command "authStatus": ${JSON.stringify(e, null, 2)}`);
+    return { loggedIn: false };
+  }
+}
+
+export async function importFollows() {
+  try {
+    return await invoke<FollowedManga[]>('import_follows', { locales: get(locales) });
+  } catch (e) {
+    error(`failed to invoke command "importFollows": ${JSON.stringify(e, null, 2)}`);
+    return [];
+  }
+}
+
+export async function getFollows() {
+  try {
+    return await invoke<FollowedManga[]>('get_follows');
+  } catch (e) {
+    error(`failed to invoke command "getFollows": ${JSON.stringify(e, null, 2)}`);
+    return [];
+  }
+}
+
+export async function syncReadMarkers(mangaId: string) {
+  try {
+    return await invoke<ReadMarkersSync>('sync_read_markers', { mangaId });
+  } catch (e) {
+    error(`failed to invoke command "syncReadMarkers": ${JSON.stringify(e, null, 2)}`);
+    return undefined;
+  }
+}
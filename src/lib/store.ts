@@ -1,5 +1,14 @@
+import { listen } from "@tauri-apps/api/event";
 import { writable } from "svelte/store";
-import { search, type MangaView } from "$lib/commands";
+import {
+  getSettings,
+  search,
+  SETTINGS_CHANGED_EVENT,
+  updateSettings,
+  type ChapterAvailability,
+  type MangaView,
+  type Settings,
+} from "$lib/commands";
 
 function createMangaList() {
   const { subscribe, set } = writable<MangaView[] | undefined>();
@@ -22,12 +31,43 @@ function createMangaList() {
 
 export const mangaList = createMangaList();
 
+export const locales = writable<string[]>(["en"]);
+
+function createSettings() {
+  const { subscribe, set } = writable<Settings | undefined>();
+
+  const apply = (settings: Settings) => {
+    set(settings);
+    locales.set(settings.locales);
+  };
+
+  getSettings().then(settings => settings && apply(settings));
+  listen<Settings>(SETTINGS_CHANGED_EVENT, event => apply(event.payload));
+
+  return {
+    subscribe,
+    save: async (settings: Settings) => {
+      const saved = await updateSettings(settings);
+      if (saved) apply(saved);
+      return saved;
+    },
+  }
+}
+
+export const settings = createSettings();
+
 export class ChapterProps {
   constructor(
     private _id: string,
-    private chapter: string,
+    private chapter: string | undefined,
+    private mangaId: string,
     private mangaName: string,
     private title?: string,
+    private volume?: string,
+    private groupId?: string,
+    private externalUrl?: string,
+    private availability: ChapterAvailability = 'available',
+    private translatedLanguage?: string,
   ) { }
 
   get id() {
@@ -35,7 +75,8 @@ export class ChapterProps {
   }
 
   get chapterName() {
-    return `Chapter ${this.chapter}${this.title ? ` - ${this.title}` : ""}`;
+    const name = this.chapter ? `Chapter ${this.chapter}` : "Oneshot";
+    return `${name}${this.title ? ` - ${this.title}` : ""}`;
   }
 
   get fullname() {
@@ -46,6 +87,13 @@ export class ChapterProps {
     return {
       id: this.id,
       fullname: this.fullname,
+      mangaId: this.mangaId,
+      mangaName: this.mangaName,
+      volume: this.volume,
+      groupId: this.groupId,
+      externalUrl: this.externalUrl,
+      availability: this.availability,
+      translatedLanguage: this.translatedLanguage,
     }
   }
 }